@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 
-use crate::process::{status::BuiltinExitStatus, VashProcess};
+use crate::{context::ShellContext, process::VashProcess};
 
 use super::BuiltinCommand;
 
@@ -13,12 +13,20 @@ impl BuiltinCommand for Cd {
         "cd"
     }
 
-    async fn execute(&self, args: &[&str]) -> VashProcess {
-        let dir = args.first().unwrap_or(&".").to_string();
-        VashProcess::adhoc_process(|_child| async {
-            std::env::set_current_dir(dir).unwrap();
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let dir = args.first().unwrap_or(&".");
 
-            BuiltinExitStatus::new_success().into()
-        })
+        // only the shell's working directory changes, vash's own stays put so
+        // that subshells can each have their own
+        match tokio::fs::canonicalize(ctx.resolve(dir)).await {
+            Ok(path) if path.is_dir() => {
+                ctx.set_var("PWD", path.to_string_lossy());
+                ctx.set_cwd(path);
+
+                VashProcess::sink()
+            }
+            Ok(_) => VashProcess::failure(format!("vash: cd: {}: Not a directory\n", dir)),
+            Err(err) => VashProcess::failure(format!("vash: cd: {}: {}\n", dir, err)),
+        }
     }
 }
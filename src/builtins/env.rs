@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+
+use crate::{context::ShellContext, parse::word::is_name, process::VashProcess};
+
+use super::{write_output, BuiltinCommand};
+
+const EXPORT_USAGE: &str = "export: usage: export [-n] [-p] [name[=value] ...]\n";
+const UNSET_USAGE: &str = "unset: usage: unset [-f] [-v] [name ...]\n";
+
+#[derive(Default)]
+pub struct Export;
+
+#[derive(Default)]
+pub struct Unset;
+
+#[async_trait]
+impl BuiltinCommand for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let mut remove = false;
+        let mut names = args;
+
+        while let Some(option) = names.first().filter(|arg| arg.starts_with('-')) {
+            match *option {
+                "-n" => remove = true,
+                "-p" => {}
+                "--" => {
+                    names = &names[1..];
+                    break;
+                }
+                _ => {
+                    return VashProcess::failure(format!(
+                        "vash: export: {}: invalid option\n{}",
+                        option, EXPORT_USAGE
+                    ))
+                }
+            }
+
+            names = &names[1..];
+        }
+
+        let mut output = String::new();
+        let mut errors = String::new();
+
+        if names.is_empty() {
+            for (name, value) in ctx.env() {
+                output.push_str(&format!("declare -x {}=\"{}\"\n", name, quote(&value)));
+            }
+        }
+
+        for arg in names {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (*arg, None),
+            };
+
+            if !is_name(name) {
+                errors.push_str(&format!(
+                    "vash: export: `{}': not a valid identifier\n",
+                    arg
+                ));
+                continue;
+            }
+
+            if remove {
+                if let Some(value) = value {
+                    ctx.set_var(name, value);
+                }
+                ctx.unexport(name);
+            } else {
+                ctx.export(name, value);
+            }
+        }
+
+        write_output(output, errors)
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        // `None` unsets a variable, or a function if there's no such variable
+        let mut functions = None;
+        let mut names = args;
+
+        while let Some(option) = names.first().filter(|arg| arg.starts_with('-')) {
+            match *option {
+                "-v" => functions = Some(false),
+                "-f" => functions = Some(true),
+                "--" => {
+                    names = &names[1..];
+                    break;
+                }
+                _ => {
+                    return VashProcess::failure(format!(
+                        "vash: unset: {}: invalid option\n{}",
+                        option, UNSET_USAGE
+                    ))
+                }
+            }
+
+            names = &names[1..];
+        }
+
+        let mut errors = String::new();
+
+        for name in names {
+            match functions {
+                Some(true) => {
+                    ctx.unset_function(name);
+                }
+                _ if !is_name(name) => {
+                    errors.push_str(&format!(
+                        "vash: unset: `{}': not a valid identifier\n",
+                        name
+                    ));
+                }
+                Some(false) => ctx.unset_var(name),
+                None if ctx.var(name).is_some() => ctx.unset_var(name),
+                None => {
+                    ctx.unset_function(name);
+                }
+            }
+        }
+
+        write_output(String::new(), errors)
+    }
+}
+
+/// Escapes `value` so that it can be put between double quotes.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn prefix_assignments_only_reach_the_command() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, r#"FOO=bar sh -c 'echo $FOO'; echo "[$FOO]""#).await,
+            "bar\n[]\n"
+        );
+        assert_eq!(
+            stdout(&ctx, r#"A=1 B="2 3" sh -c 'echo $A $B'"#).await,
+            "1 2 3\n"
+        );
+        assert_eq!(ctx.var("FOO"), None);
+    }
+
+    #[tokio::test]
+    async fn export_and_unset() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"x=1; sh -c 'echo "[$x]"'; export x; sh -c 'echo "[$x]"'"#
+            )
+            .await,
+            "[]\n[1]\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"export y='a "b"'; export | grep '^declare -x y='; unset y; sh -c 'echo "[$y]"'; echo "[$y]""#
+            )
+            .await,
+            "declare -x y=\"a \\\"b\\\"\"\n[]\n[]\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"export z=1; export -n z; sh -c 'echo "[$z]"'; echo $z"#
+            )
+            .await,
+            "[]\n1\n"
+        );
+
+        let output = run(&ctx, "export 1x=2").await;
+        assert_eq!(
+            output.stderr,
+            "vash: export: `1x=2': not a valid identifier\n"
+        );
+        assert_eq!(output.status, Some(1));
+    }
+}
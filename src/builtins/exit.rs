@@ -1,6 +1,9 @@
 use async_trait::async_trait;
 
-use crate::process::VashProcess;
+use crate::{
+    context::{Control, ShellContext},
+    process::{child::VashChild, read::VashRead, status::BuiltinExitStatus, VashProcess},
+};
 
 use super::BuiltinCommand;
 
@@ -13,8 +16,33 @@ impl BuiltinCommand for Exit {
         "exit"
     }
 
-    async fn execute(&self, _args: &[&str]) -> VashProcess {
-        // TODO: exit code
-        panic!("goodbye")
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        if args.len() > 1 {
+            return VashProcess::failure("vash: exit: too many arguments\n");
+        }
+
+        let mut errors = String::new();
+
+        let status = match args.first().map(|arg| arg.parse::<i64>()) {
+            None => ctx.last_status(),
+            // like return, only the lowest byte is kept
+            Some(Ok(status)) => (status & 0xff) as i32,
+            // the shell still exits, just not successfully
+            Some(Err(_)) => {
+                errors = format!("vash: exit: {}: numeric argument required\n", args[0]);
+                2
+            }
+        };
+
+        // whatever the shell is running stops here, and the shell itself
+        // exits once it sees this. subshells and the like run in a copy of
+        // the shell, so only the copy is left
+        ctx.set_control(Control::Exit(status));
+
+        VashProcess {
+            stderr: VashRead::Canned(errors.into_bytes()),
+            child: VashChild::PreExecuted(BuiltinExitStatus::from_code(status)),
+            ..VashProcess::sink()
+        }
     }
 }
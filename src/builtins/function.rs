@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+
+use crate::{
+    context::{Control, ShellContext},
+    parse::word::is_name,
+    process::{child::VashChild, status::BuiltinExitStatus, VashProcess},
+};
+
+use super::BuiltinCommand;
+
+#[derive(Default)]
+pub struct Local;
+
+#[derive(Default)]
+pub struct Return;
+
+#[async_trait]
+impl BuiltinCommand for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        if !ctx.in_function() {
+            return VashProcess::failure("vash: local: can only be used in a function\n");
+        }
+
+        let mut errors = String::new();
+
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (*arg, None),
+            };
+
+            if !is_name(name) {
+                errors.push_str(&format!("vash: local: `{}': not a valid identifier\n", arg));
+                continue;
+            }
+
+            ctx.set_local(name, value);
+        }
+
+        if errors.is_empty() {
+            VashProcess::sink()
+        } else {
+            VashProcess::failure(errors)
+        }
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        if !ctx.in_function() {
+            return VashProcess::failure("vash: return: can only `return' from a function\n");
+        }
+
+        let status = match args.first().map(|arg| arg.parse::<i64>()) {
+            None => ctx.last_status(),
+            // like exit statuses, only the lowest byte is kept
+            Some(Ok(status)) => (status & 0xff) as i32,
+            Some(Err(_)) => {
+                return VashProcess::failure(format!(
+                    "vash: return: {}: numeric argument required\n",
+                    args[0]
+                ))
+            }
+        };
+
+        ctx.set_control(Control::Return(status));
+
+        VashProcess {
+            child: VashChild::PreExecuted(BuiltinExitStatus::from_code(status)),
+            ..VashProcess::sink()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn functions_get_their_own_positional_parameters() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"f() { echo $# "$1"; for arg in "$@"; do echo "[$arg]"; done; }; f a "b c""#
+            )
+            .await,
+            "2 a\n[a]\n[b c]\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "function g { echo $1; }; g x; echo \"[$1]\"").await,
+            "x\n[]\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "count() { if [ $1 -gt 0 ]; then echo $1; count $(($1 - 1)); fi; }; count 3"
+            )
+            .await,
+            "3\n2\n1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn return_and_local() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "f() { return 3; echo no; }; f; echo $?").await,
+            "3\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "x=global; f() { local x=local; echo $x; }; f; echo $x"
+            )
+            .await,
+            "local\nglobal\n"
+        );
+
+        let output = run(&ctx, "return 1").await;
+        assert_eq!(
+            output.stderr,
+            "vash: return: can only `return' from a function\n"
+        );
+        assert_eq!(output.status, Some(1));
+    }
+}
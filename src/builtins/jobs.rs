@@ -0,0 +1,564 @@
+use async_trait::async_trait;
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::{io::AsyncWriteExt, sync::oneshot};
+
+use crate::{
+    cmd::delegate::DelegateMessage,
+    context::ShellContext,
+    job::{Job, JobState, JobTable, TERMINATED},
+    process::{status::BuiltinExitStatus, PseudoChild, VashProcess},
+};
+
+use super::{write_output, BuiltinCommand};
+
+const JOBS_USAGE: &str = "jobs: usage: jobs [-lprs] [jobspec ...]\n";
+const KILL_USAGE: &str =
+    "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]\n";
+const DISOWN_USAGE: &str = "disown: usage: disown [-ar] [jobspec ...]\n";
+
+#[derive(Default)]
+pub struct Jobs;
+
+#[derive(Default)]
+pub struct Fg;
+
+#[derive(Default)]
+pub struct Bg;
+
+#[derive(Default)]
+pub struct Wait;
+
+#[derive(Default)]
+pub struct Kill;
+
+#[derive(Default)]
+pub struct Disown;
+
+/// What a job control builtin writes out once it has run.
+#[derive(Default)]
+struct Report {
+    output: String,
+    errors: String,
+    /// Written out along with the errors, but without failing.
+    warnings: String,
+}
+
+impl Report {
+    fn error(&mut self, name: &str, message: &str) {
+        self.errors
+            .push_str(&format!("vash: {}: {}\n", name, message));
+    }
+
+    fn warn(&mut self, name: &str, message: &str) {
+        self.warnings
+            .push_str(&format!("vash: {}: {}\n", name, message));
+    }
+}
+
+/// Runs `f` on the job table once the builtin starts running, then writes
+/// out whatever it reports.
+fn with_jobs<F>(ctx: &ShellContext, name: &'static str, f: F) -> VashProcess
+where
+    F: FnOnce(&mut JobTable) -> Report + Send + 'static,
+{
+    let ctx = ctx.clone();
+
+    VashProcess::adhoc_process(move |mut child| async move {
+        let report = ctx.with_jobs(f).await.unwrap_or_else(|| {
+            let mut report = Report::default();
+            report.error(name, "no job control");
+            report
+        });
+
+        child
+            .stderr
+            .write_all(report.warnings.as_bytes())
+            .await
+            .ok();
+
+        write_output(report.output, report.errors)
+            .forward(&mut child)
+            .await
+    })
+}
+
+/// Splits the options off of the front of `args`, returning them as one
+/// string of letters along with the rest of the arguments.
+fn options<'a>(args: &'a [&'a str]) -> (String, &'a [&'a str]) {
+    let mut options = String::new();
+    let mut args = args;
+
+    while let Some(arg) = args.first() {
+        if *arg == "--" {
+            args = &args[1..];
+            break;
+        }
+
+        match arg.strip_prefix('-') {
+            Some(letters) if !letters.is_empty() => options.push_str(letters),
+            _ => break,
+        }
+
+        args = &args[1..];
+    }
+
+    (options, args)
+}
+
+/// The job specs in `args`, or the current job if there are none.
+fn specs(args: &[&str]) -> Vec<String> {
+    if args.is_empty() {
+        vec![String::from("%%")]
+    } else {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let (options, specs) = options(args);
+
+        if let Some(option) = options.chars().find(|c| !"lprs".contains(*c)) {
+            return VashProcess::failure(format!(
+                "vash: jobs: -{}: invalid option\n{}",
+                option, JOBS_USAGE
+            ));
+        }
+
+        let long = options.contains('l');
+        let pids = options.contains('p');
+        let state = match (options.contains('r'), options.contains('s')) {
+            (true, false) => Some(JobState::Running),
+            (false, true) => Some(JobState::Stopped),
+            _ => None,
+        };
+
+        let specs = specs
+            .iter()
+            .map(|spec| spec.to_string())
+            .collect::<Vec<_>>();
+
+        with_jobs(ctx, "jobs", move |table| {
+            let mut report = Report::default();
+
+            let ids = if specs.is_empty() {
+                table.jobs().iter().map(|job| job.id).collect()
+            } else {
+                let mut ids = Vec::new();
+
+                for spec in &specs {
+                    match table.find(spec) {
+                        Ok(id) => ids.push(id),
+                        Err(err) => report.error("jobs", &err),
+                    }
+                }
+
+                ids
+            };
+
+            for job in ids.into_iter().filter_map(|id| table.get(id)) {
+                if matches!(state, Some(state) if state != job.state) {
+                    continue;
+                }
+
+                if pids {
+                    if let Some(pid) = job.pid {
+                        report.output.push_str(&format!("{}\n", pid));
+                    }
+                } else {
+                    let line = table.describe(job, &job.state.to_string(), long);
+                    report.output.push_str(&format!("{}\n", line));
+                }
+            }
+
+            report
+        })
+    }
+}
+
+/// A job brought to the foreground by `fg`, which is terminated if `fg` is.
+struct Foreground {
+    job: Job,
+    finished: bool,
+}
+
+impl Foreground {
+    /// Streams the job's output through `child` until it finishes, returning
+    /// its exit status.
+    async fn wait(mut self, child: &mut PseudoChild) -> i32 {
+        let status = loop {
+            match self.job.delegate.rx.recv().await {
+                Some(DelegateMessage::Stdout(data)) => {
+                    child.stdout.write_all(&data).await.ok();
+                }
+                Some(DelegateMessage::Stderr(data)) => {
+                    child.stderr.write_all(&data).await.ok();
+                }
+                Some(DelegateMessage::Exit(status)) => break status.status_code(),
+                Some(DelegateMessage::Error(_)) | None => break TERMINATED,
+            }
+        };
+
+        self.finished = true;
+        self.job.finish(status);
+
+        status
+    }
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.job.signal(Signal::SIGTERM).ok();
+        }
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let spec = specs(args).swap_remove(0);
+        let ctx = ctx.clone();
+
+        VashProcess::adhoc_process(|mut child| async move {
+            let job = ctx
+                .with_jobs(move |table| {
+                    let id = table.find(&spec)?;
+                    Ok(table.remove(id).unwrap())
+                })
+                .await
+                .unwrap_or_else(|| Err(String::from("no job control")));
+
+            let mut job = match job {
+                Ok(job) => job,
+                Err(err) => {
+                    let message = format!("vash: fg: {}\n", err);
+                    child.stderr.write_all(message.as_bytes()).await.ok();
+                    return BuiltinExitStatus::new_failure().into();
+                }
+            };
+
+            let command = format!("{}\n", job.command);
+            child.stdout.write_all(command.as_bytes()).await.ok();
+
+            if job.state == JobState::Stopped {
+                job.signal(Signal::SIGCONT).ok();
+            }
+
+            let foreground = Foreground {
+                job,
+                finished: false,
+            };
+
+            BuiltinExitStatus::from_code(foreground.wait(&mut child).await).into()
+        })
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let specs = specs(args);
+
+        with_jobs(ctx, "bg", move |table| {
+            let mut report = Report::default();
+
+            for spec in &specs {
+                let id = match table.find(spec) {
+                    Ok(id) => id,
+                    Err(err) => {
+                        report.error("bg", &err);
+                        continue;
+                    }
+                };
+
+                let job = table.get_mut(id).unwrap();
+
+                if job.state == JobState::Running {
+                    // not worth failing over
+                    report.warn("bg", &format!("job {} already in background", id));
+                    continue;
+                }
+
+                if let Err(err) = job.signal(Signal::SIGCONT) {
+                    report.error("bg", &err);
+                    continue;
+                }
+
+                report
+                    .output
+                    .push_str(&format!("[{}]+ {} &\n", id, job.command));
+                table.make_current(id);
+            }
+
+            report
+        })
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Wait {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let targets = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let ctx = ctx.clone();
+
+        VashProcess::adhoc_process(|mut child| async move {
+            let all = targets.is_empty();
+
+            let waits = ctx
+                .with_jobs(move |table| {
+                    let ids: Vec<_> = if all {
+                        table.jobs().iter().map(|job| Ok(job.id)).collect()
+                    } else {
+                        targets.iter().map(|target| find(table, target)).collect()
+                    };
+
+                    ids.into_iter()
+                        .map(|id| {
+                            let (waiter, wait) = oneshot::channel();
+                            table.get_mut(id?).unwrap().waiters.push(waiter);
+                            Ok(wait)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await
+                .unwrap_or_default();
+
+            let mut status = 0;
+
+            for wait in waits {
+                status = match wait {
+                    // the job was disowned while it was being waited for
+                    Ok(wait) => wait.await.unwrap_or(127),
+                    Err((message, status)) => {
+                        let message = format!("vash: wait: {}\n", message);
+                        child.stderr.write_all(message.as_bytes()).await.ok();
+                        status
+                    }
+                };
+            }
+
+            // waiting for every job always succeeds
+            if all {
+                status = 0;
+            }
+
+            BuiltinExitStatus::from_code(status).into()
+        })
+    }
+}
+
+/// Finds the job `target` refers to for `wait`, which is either a job spec
+/// or a process id.
+fn find(table: &JobTable, target: &str) -> Result<usize, (String, i32)> {
+    if target.starts_with('%') {
+        return table.find(target).map_err(|err| (err, 127));
+    }
+
+    match target.parse::<u32>() {
+        Ok(pid) => table
+            .by_pid(pid)
+            .ok_or_else(|| (format!("pid {} is not a child of this shell", pid), 127)),
+        Err(_) => Err((format!("`{}': not a pid or valid job spec", target), 2)),
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Kill {
+    fn name(&self) -> &'static str {
+        "kill"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        if matches!(args.first(), Some(&"-l" | &"-L")) {
+            return list_signals(&args[1..]);
+        }
+
+        let (signal, targets) = match args {
+            [] => return VashProcess::failure(KILL_USAGE),
+            ["-s" | "-n", signal, targets @ ..] => (*signal, targets),
+            ["--", targets @ ..] => ("TERM", targets),
+            [option, targets @ ..] if option.len() > 1 && option.starts_with('-') => {
+                (&option[1..], targets)
+            }
+            targets => ("TERM", targets),
+        };
+
+        let Some(signal) = parse_signal(signal) else {
+            return VashProcess::failure(format!(
+                "vash: kill: {}: invalid signal specification\n",
+                signal
+            ));
+        };
+
+        if targets.is_empty() {
+            return VashProcess::failure(KILL_USAGE);
+        }
+
+        let targets = targets
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        let ctx = ctx.clone();
+
+        VashProcess::adhoc_process(move |mut child| async move {
+            let mut errors = String::new();
+
+            for target in targets {
+                let result = if target.starts_with('%') {
+                    ctx.with_jobs(move |table| {
+                        let id = table.find(&target)?;
+
+                        match signal {
+                            Some(signal) => table.get_mut(id).unwrap().signal(signal),
+                            None => Ok(()),
+                        }
+                    })
+                    .await
+                    .unwrap_or_else(|| Err(String::from("no job control")))
+                } else {
+                    match target.parse::<i32>() {
+                        Ok(pid) => kill(Pid::from_raw(pid), signal)
+                            .map_err(|err| format!("({}) - {}", pid, err.desc())),
+                        Err(_) => Err(format!("{}: arguments must be process or job IDs", target)),
+                    }
+                };
+
+                if let Err(err) = result {
+                    errors.push_str(&format!("vash: kill: {}\n", err));
+                }
+            }
+
+            write_output(String::new(), errors)
+                .forward(&mut child)
+                .await
+        })
+    }
+}
+
+/// Parses a signal given by name, with or without the `SIG`, or by number.
+/// Signal `0` is `Some(None)`, which checks that a process exists without
+/// sending it anything.
+fn parse_signal(signal: &str) -> Option<Option<Signal>> {
+    if let Ok(number) = signal.parse::<i32>() {
+        return match number {
+            0 => Some(None),
+            number => Signal::try_from(number).ok().map(Some),
+        };
+    }
+
+    let name = signal.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+
+    format!("SIG{}", name).parse().ok().map(Some)
+}
+
+/// `kill -l`, which lists every signal, or converts between the names and
+/// numbers of the signals given.
+fn list_signals(args: &[&str]) -> VashProcess {
+    let mut output = String::new();
+    let mut errors = String::new();
+
+    if args.is_empty() {
+        let names = Signal::iterator()
+            .map(|signal| signal.as_str().trim_start_matches("SIG"))
+            .collect::<Vec<_>>();
+
+        output.push_str(&format!("{}\n", names.join(" ")));
+    }
+
+    for arg in args {
+        match (arg.parse::<i32>(), parse_signal(arg)) {
+            // exit statuses of signalled processes can be turned back into
+            // the signal's name
+            (Ok(number), _) => match Signal::try_from(number % 128) {
+                Ok(signal) => {
+                    output.push_str(&format!("{}\n", signal.as_str().trim_start_matches("SIG")))
+                }
+                Err(_) => errors.push_str(&format!(
+                    "vash: kill: {}: invalid signal specification\n",
+                    arg
+                )),
+            },
+            (Err(_), Some(Some(signal))) => output.push_str(&format!("{}\n", signal as i32)),
+            (Err(_), _) => errors.push_str(&format!(
+                "vash: kill: {}: invalid signal specification\n",
+                arg
+            )),
+        }
+    }
+
+    write_output(output, errors)
+}
+
+#[async_trait]
+impl BuiltinCommand for Disown {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let (options, specs) = options(args);
+
+        if let Some(option) = options.chars().find(|c| !"ar".contains(*c)) {
+            return VashProcess::failure(format!(
+                "vash: disown: -{}: invalid option\n{}",
+                option, DISOWN_USAGE
+            ));
+        }
+
+        let every = options.contains('a');
+        let running = options.contains('r');
+        let specs = self::specs(specs);
+
+        with_jobs(ctx, "disown", move |table| {
+            let mut report = Report::default();
+
+            let ids = if every || running {
+                table
+                    .jobs()
+                    .iter()
+                    .filter(|job| !running || job.state == JobState::Running)
+                    .map(|job| job.id)
+                    .collect()
+            } else {
+                let mut ids = Vec::new();
+
+                for spec in &specs {
+                    match table.find(spec) {
+                        Ok(id) => ids.push(id),
+                        Err(err) => report.error("disown", &err),
+                    }
+                }
+
+                ids
+            };
+
+            for id in ids {
+                table.disown(id);
+            }
+
+            report
+        })
+    }
+}
@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+
+use crate::{
+    context::{Control, ShellContext},
+    process::{read::VashRead, VashProcess},
+};
+
+use super::BuiltinCommand;
+
+#[derive(Default)]
+pub struct Break;
+
+#[derive(Default)]
+pub struct Continue;
+
+#[async_trait]
+impl BuiltinCommand for Break {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        control_loop(ctx, self.name(), args, Control::Break)
+    }
+}
+
+#[async_trait]
+impl BuiltinCommand for Continue {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        control_loop(ctx, self.name(), args, Control::Continue)
+    }
+}
+
+/// Leaves (or skips to the next iteration of) the `N`th enclosing loop, where
+/// `N` is the first argument and defaults to 1.
+fn control_loop(
+    ctx: &ShellContext,
+    name: &str,
+    args: &[&str],
+    control: fn(usize) -> Control,
+) -> VashProcess {
+    let levels = match args.first().map(|arg| arg.parse::<i64>()) {
+        None => 1,
+        Some(Ok(levels)) if levels >= 1 => levels as usize,
+        Some(Ok(levels)) => {
+            return VashProcess::failure(format!(
+                "vash: {}: {}: loop count out of range\n",
+                name, levels
+            ))
+        }
+        Some(Err(_)) => {
+            return VashProcess::failure(format!(
+                "vash: {}: {}: numeric argument required\n",
+                name, args[0]
+            ))
+        }
+    };
+
+    let depth = ctx.loop_depth();
+
+    if depth == 0 {
+        return VashProcess {
+            stderr: VashRead::Canned(
+                format!(
+                    "vash: {}: only meaningful in a `for', `while', or `until' loop\n",
+                    name
+                )
+                .into_bytes(),
+            ),
+            ..VashProcess::sink()
+        };
+    }
+
+    ctx.set_control(control(levels.min(depth)));
+
+    VashProcess::sink()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn loops() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "for i in 1 2 3; do echo $i; done").await,
+            "1\n2\n3\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "i=0; while [ $i -lt 2 ]; do echo $i; i=$((i + 1)); done"
+            )
+            .await,
+            "0\n1\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "i=0; until [ $i -ge 2 ]; do echo $i; i=$((i + 1)); done"
+            )
+            .await,
+            "0\n1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn break_and_continue() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                "for i in 1 2 3 4; do if [ $i = 2 ]; then continue; fi; if [ $i = 4 ]; then break; fi; echo $i; done"
+            )
+            .await,
+            "1\n3\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "for a in x y; do for b in 1 2; do echo $a$b; break 2; done; done; echo out"
+            )
+            .await,
+            "x1\nout\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "for a in x y; do for b in 1 2; do echo $a$b; continue 2; echo no; done; done"
+            )
+            .await,
+            "x1\ny1\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "while true; do break 5; done; echo out").await,
+            "out\n"
+        );
+
+        let output = run(&ctx, "for i in 1; do break 0; done").await;
+        assert_eq!(output.stderr, "vash: break: 0: loop count out of range\n");
+        assert_eq!(output.status, Some(1));
+    }
+}
@@ -1,18 +1,28 @@
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
 use strum::{EnumIter, IntoEnumIterator};
+use tokio::io::AsyncWriteExt;
 
-use crate::process::VashProcess;
+use crate::{
+    context::ShellContext,
+    process::{status::BuiltinExitStatus, VashProcess},
+};
 
 pub mod cd;
+pub mod env;
 pub mod exit;
+pub mod function;
+pub mod jobs;
+pub mod loop_control;
 pub mod pwd;
+pub mod set;
+pub mod shopt;
 
 #[async_trait]
 #[enum_dispatch(BuiltinCommands)]
 pub trait BuiltinCommand {
     fn name(&self) -> &'static str;
-    async fn execute(&self, args: &[&str]) -> VashProcess;
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess;
 }
 
 #[enum_dispatch]
@@ -21,6 +31,20 @@ pub enum BuiltinCommands {
     Cd(cd::Cd),
     Pwd(pwd::Pwd),
     Exit(exit::Exit),
+    Shopt(shopt::Shopt),
+    Set(set::Set),
+    Break(loop_control::Break),
+    Continue(loop_control::Continue),
+    Local(function::Local),
+    Return(function::Return),
+    Export(env::Export),
+    Unset(env::Unset),
+    Jobs(jobs::Jobs),
+    Fg(jobs::Fg),
+    Bg(jobs::Bg),
+    Wait(jobs::Wait),
+    Kill(jobs::Kill),
+    Disown(jobs::Disown),
 }
 
 impl BuiltinCommands {
@@ -28,3 +52,18 @@ impl BuiltinCommands {
         Self::iter().find(|cmd| cmd.name() == name)
     }
 }
+
+/// A process that writes `output` and `errors`, failing if there were any
+/// errors.
+fn write_output(output: String, errors: String) -> VashProcess {
+    VashProcess::adhoc_process(|mut child| async move {
+        child.stdout.write_all(output.as_bytes()).await.ok();
+        child.stderr.write_all(errors.as_bytes()).await.ok();
+
+        if errors.is_empty() {
+            BuiltinExitStatus::new_success().into()
+        } else {
+            BuiltinExitStatus::new_failure().into()
+        }
+    })
+}
@@ -1,7 +1,10 @@
 use async_trait::async_trait;
 use tokio::io::AsyncWriteExt;
 
-use crate::process::{status::BuiltinExitStatus, VashProcess};
+use crate::{
+    context::ShellContext,
+    process::{status::BuiltinExitStatus, VashProcess},
+};
 
 use super::BuiltinCommand;
 
@@ -14,11 +17,11 @@ impl BuiltinCommand for Pwd {
         "pwd"
     }
 
-    async fn execute(&self, _args: &[&str]) -> VashProcess {
-        VashProcess::adhoc_process(|child| async {
-            let mut stdout = child.stdout;
+    async fn execute(&self, ctx: &ShellContext, _args: &[&str]) -> VashProcess {
+        let cwd = ctx.cwd();
 
-            let cwd = std::env::current_dir().unwrap();
+        VashProcess::adhoc_process(|child| async move {
+            let mut stdout = child.stdout;
 
             let output = format!("{}\n", cwd.display());
 
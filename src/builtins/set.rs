@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+
+use crate::{
+    context::{ShellContext, ShellOptions},
+    process::VashProcess,
+};
+
+use super::{write_output, BuiltinCommand};
+
+const SET_USAGE: &str = "set: usage: set [-o option-name] [+o option-name]\n";
+
+#[derive(Default)]
+pub struct Set;
+
+#[async_trait]
+impl BuiltinCommand for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let mut output = String::new();
+        let mut errors = String::new();
+        let mut args = args;
+
+        while let Some(arg) = args.first() {
+            let value = match *arg {
+                "-o" => true,
+                "+o" => false,
+                _ => {
+                    return VashProcess::failure(format!(
+                        "vash: set: {}: invalid option\n{}",
+                        arg, SET_USAGE
+                    ))
+                }
+            };
+
+            match args.get(1) {
+                Some(name) => {
+                    if !ShellOptions::SET_NAMES.contains(name) || !ctx.set_option(name, value) {
+                        errors.push_str(&format!("vash: set: {}: invalid option name\n", name));
+                    }
+
+                    args = &args[2..];
+                }
+                // `-o` lists the options and `+o` lists the commands that
+                // would set them back to how they are now
+                None => {
+                    let options = ctx.options();
+
+                    for name in ShellOptions::SET_NAMES {
+                        let state = options.get(name).unwrap_or_default();
+
+                        output.push_str(&match (value, state) {
+                            (true, true) => format!("{:<15}\ton\n", name),
+                            (true, false) => format!("{:<15}\toff\n", name),
+                            (false, true) => format!("set -o {}\n", name),
+                            (false, false) => format!("set +o {}\n", name),
+                        });
+                    }
+
+                    args = &args[1..];
+                }
+            }
+        }
+
+        write_output(output, errors)
+    }
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    context::{ShellContext, ShellOptions},
+    process::{status::BuiltinExitStatus, VashProcess},
+};
+
+use super::BuiltinCommand;
+
+#[derive(Default)]
+pub struct Shopt;
+
+#[async_trait]
+impl BuiltinCommand for Shopt {
+    fn name(&self) -> &'static str {
+        "shopt"
+    }
+
+    async fn execute(&self, ctx: &ShellContext, args: &[&str]) -> VashProcess {
+        let (value, names) = match args.first() {
+            Some(&"-s") => (Some(true), &args[1..]),
+            Some(&"-u") => (Some(false), &args[1..]),
+            _ => (None, args),
+        };
+
+        let options = ctx.options();
+        let mut output = String::new();
+        let mut errors = String::new();
+        // querying options by name fails unless they're all on
+        let mut all_on = true;
+
+        if names.is_empty() {
+            // list every option, or only those that are already set/unset
+            for name in ShellOptions::NAMES {
+                let state = options.get(name).unwrap_or_default();
+
+                if value.is_none() || value == Some(state) {
+                    output.push_str(&format!("{}\t{}\n", name, on_off(state)));
+                }
+            }
+        }
+
+        for name in names {
+            // the options of `set -o` can't be set from here
+            let found = ShellOptions::NAMES.contains(name)
+                && match value {
+                    Some(value) => ctx.set_option(name, value),
+                    None => match options.get(name) {
+                        Some(state) => {
+                            all_on &= state;
+                            output.push_str(&format!("{}\t{}\n", name, on_off(state)));
+                            true
+                        }
+                        None => false,
+                    },
+                };
+
+            if !found {
+                errors.push_str(&format!(
+                    "vash: shopt: {}: invalid shell option name\n",
+                    name
+                ));
+            }
+        }
+
+        VashProcess::adhoc_process(move |mut child| async move {
+            child.stdout.write_all(output.as_bytes()).await.ok();
+            child.stderr.write_all(errors.as_bytes()).await.ok();
+
+            if errors.is_empty() && all_on {
+                BuiltinExitStatus::new_success().into()
+            } else {
+                BuiltinExitStatus::new_failure().into()
+            }
+        })
+    }
+}
+
+fn on_off(state: bool) -> &'static str {
+    if state {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cmd::tests::run, context::ShellContext};
+
+    #[tokio::test]
+    async fn querying_fails_unless_every_option_is_on() {
+        let ctx = ShellContext::default();
+
+        let output = run(&ctx, "shopt nullglob").await;
+        assert_eq!(output.stdout, "nullglob\toff\n");
+        assert_eq!(output.status, Some(1));
+
+        assert_eq!(run(&ctx, "shopt -s nullglob").await.status, Some(0));
+        assert_eq!(run(&ctx, "shopt nullglob").await.status, Some(0));
+        assert_eq!(run(&ctx, "shopt nullglob failglob").await.status, Some(1));
+        assert_eq!(run(&ctx, "shopt").await.status, Some(0));
+
+        let output = run(&ctx, "shopt nosuchopt").await;
+        assert_eq!(
+            output.stderr,
+            "vash: shopt: nosuchopt: invalid shell option name\n"
+        );
+        assert_eq!(output.status, Some(1));
+    }
+}
@@ -5,13 +5,16 @@ use tokio::{
     select,
 };
 
-use crate::{prelude::*, process::VashProcess};
+use crate::{
+    prelude::*,
+    process::{status::VashExitStatus, VashProcess},
+};
 
 #[derive(Debug)]
 pub enum DelegateMessage {
     Stdout(Vec<u8>),
     Stderr(Vec<u8>),
-    Exit(Option<i32>),
+    Exit(VashExitStatus),
     Error(String),
 }
 
@@ -65,10 +68,17 @@ impl ExecutionDelegate {
         let (mtx, mrx) = unbounded_channel();
         let (ctx, mut crx) = unbounded_channel();
 
+        // the shell stops listening as soon as it terminates a command, so
+        // none of the messages sent here are guaranteed to be received
         tokio::task::spawn(async move {
             let mut stdout_buf = Vec::new();
             let mut stderr_buf = Vec::new();
 
+            // a stream can close long before the process exits (e.g. when it
+            // is redirected), so stop polling it once it does
+            let mut stdout_done = false;
+            let mut stderr_done = false;
+
             loop {
                 select! {
                     Some(cmd) = crx.recv() => {
@@ -78,45 +88,51 @@ impl ExecutionDelegate {
                                 exec.stdin.flush().await.unwrap();
                             }
                             DelegateCommand::Signal(sig) => {
-                                exec.child.signal(sig).await.unwrap();
+                                // the process may have exited since the
+                                // signal was sent
+                                if let Err(err) = exec.child.signal(sig).await {
+                                    trace!("failed to send {}: {}", sig, err);
+                                }
                             }
                         }
                     }
-                    Ok(stdout_len) = exec.stdout.read_buf(&mut stdout_buf) => {
+                    Ok(stdout_len) = exec.stdout.read_buf(&mut stdout_buf), if !stdout_done => {
                         if stdout_len == 0 {
+                            stdout_done = true;
                             continue;
                         }
 
-                        mtx.send(DelegateMessage::Stdout(std::mem::take(&mut stdout_buf))).unwrap();
+                        mtx.send(DelegateMessage::Stdout(std::mem::take(&mut stdout_buf))).ok();
                     }
-                    Ok(stderr_len) = exec.stderr.read_buf(&mut stderr_buf) => {
+                    Ok(stderr_len) = exec.stderr.read_buf(&mut stderr_buf), if !stderr_done => {
                         if stderr_len == 0 {
+                            stderr_done = true;
                             continue;
                         }
 
-                        mtx.send(DelegateMessage::Stderr(std::mem::take(&mut stderr_buf))).unwrap();
+                        mtx.send(DelegateMessage::Stderr(std::mem::take(&mut stderr_buf))).ok();
                     }
                     output = exec.child.wait() => {
                         // drain the remaining stdout/stderr
                         if let Ok(len) = exec.stdout.read_to_end(&mut stdout_buf).await {
                             if len > 0 {
-                                mtx.send(DelegateMessage::Stdout(std::mem::take(&mut stdout_buf))).unwrap();
+                                mtx.send(DelegateMessage::Stdout(std::mem::take(&mut stdout_buf))).ok();
                             }
                         }
 
                         if let Ok(len) = exec.stderr.read_to_end(&mut stderr_buf).await {
                             if len > 0 {
-                                mtx.send(DelegateMessage::Stderr(std::mem::take(&mut stderr_buf))).unwrap();
+                                mtx.send(DelegateMessage::Stderr(std::mem::take(&mut stderr_buf))).ok();
                             }
                         }
 
                         match output {
                             Ok(exit) => {
-                                mtx.send(DelegateMessage::Exit(exit.code())).unwrap();
+                                mtx.send(DelegateMessage::Exit(exit)).ok();
                                 break;
                             }
                             Err(err) => {
-                                mtx.send(DelegateMessage::Error(err.to_string())).unwrap();
+                                mtx.send(DelegateMessage::Error(err.to_string())).ok();
                                 break;
                             }
                         }
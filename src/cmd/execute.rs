@@ -1,128 +1,585 @@
-use std::{ops::Deref, process::Stdio};
+use std::{ffi::OsStr, io, process::Stdio, sync::Arc};
 
 use async_recursion::async_recursion;
-use tokio::{
-    io::{AsyncRead, AsyncWrite},
-    process::Command,
-};
+use tokio::process::Command;
 
-use super::execution_plan::{ExecutionPlan, PipeType};
+use super::{
+    execution_plan::{CaseTerminator, ExecutionPlan, SimpleCommand},
+    pipeline::Pipeline,
+};
 use crate::{
     builtins::{BuiltinCommand, BuiltinCommands},
-    process::VashProcess,
+    context::{Control, ShellContext},
+    expand::{pattern::Pattern, Expander},
+    job::BackgroundJob,
+    process::{
+        child::VashChild,
+        status::{BuiltinExitStatus, VashExitStatus},
+        write::VashWrite,
+        PseudoChild, VashProcess,
+    },
 };
 
 impl ExecutionPlan {
-    #[async_recursion(?Send)]
-    pub async fn execute(&self) -> VashProcess {
+    #[async_recursion]
+    pub async fn execute(&self, ctx: &ShellContext) -> VashProcess {
         match self {
-            Self::Execute(cmd, args) => {
-                if let Some(builtin) = BuiltinCommands::from_name(cmd) {
-                    return builtin
-                        // this is not optimal
-                        .execute(&args.iter().map(Deref::deref).collect::<Vec<_>>())
-                        .await;
-                }
+            Self::Execute(cmd) => cmd.execute(ctx).await,
+            Self::And(left, right) | Self::Or(left, right) => {
+                // whether the right side runs when the left side succeeds,
+                // rather than when it fails
+                let and = matches!(self, Self::And(..));
+                let (left, right) = (left.clone(), right.clone());
+                let ctx = ctx.clone();
 
-                let mut cmd = Command::new(cmd);
-                cmd.args(args);
+                VashProcess::adhoc_process(move |mut child| async move {
+                    trace!("AND/OR: executing left");
+                    let status = left.run_in(&ctx, &mut child).await;
 
-                cmd.stdin(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped());
+                    if ctx.control_pending() || status.success() != and {
+                        return status;
+                    }
 
-                trace!("spawning command: {:?}", cmd);
+                    trace!("AND/OR: executing right");
+                    right.run_in(&ctx, &mut child).await
+                })
+            }
+            Self::Sequence(plans) => {
+                let plans = plans.clone();
+                let ctx = ctx.clone();
 
-                let mut child = cmd.spawn().unwrap();
+                VashProcess::adhoc_process(|mut child| async move {
+                    let mut status = BuiltinExitStatus::new_success().into();
 
-                let stdin = child.stdin.take().unwrap().into();
-                let stdout = child.stdout.take().unwrap().into();
-                let stderr = child.stderr.take().unwrap().into();
+                    for plan in &plans {
+                        trace!("SEQUENCE: executing {:?}", plan);
+                        status = plan.run_in(&ctx, &mut child).await;
 
-                VashProcess {
-                    stdin,
-                    stdout,
-                    stderr,
-                    child: child.into(),
-                }
+                        if ctx.control_pending() {
+                            break;
+                        }
+                    }
+
+                    status
+                })
             }
-            Self::And(left, right) => {
-                trace!("AND: executing left");
-                let mut left = left.execute().await;
+            Self::If(clause) => {
+                let clause = clause.clone();
+                let ctx = ctx.clone();
 
-                trace!("AND: waiting for left to finish");
-                let res = left.child.wait().await;
+                VashProcess::adhoc_process(|mut child| async move {
+                    for (condition, body) in &clause.branches {
+                        trace!("IF: checking {:?}", condition);
+                        let status = condition.run_in(&ctx, &mut child).await;
 
-                trace!("AND: left finished, checking exit status");
-                match res {
-                    Ok(exit) if exit.success() => right.execute().await,
-                    Ok(_) => left,
-                    Err(_) => left,
+                        if ctx.control_pending() {
+                            return status;
+                        } else if status.success() {
+                            return body.run_in(&ctx, &mut child).await;
+                        }
+                    }
+
+                    match &clause.otherwise {
+                        Some(body) => body.run_in(&ctx, &mut child).await,
+                        None => BuiltinExitStatus::new_success().into(),
+                    }
+                })
+            }
+            Self::Pipe(..) => {
+                let mut stages = Vec::new();
+
+                // the stages run at the same time, so each one gets its own
+                // copy of the shell like a subshell would
+                for plan in self.pipeline_stages() {
+                    trace!("PIPELINE: spawning {:?}", plan);
+                    stages.push(plan.execute(&ctx.fork()).await);
                 }
+
+                Pipeline::new(stages).into_process(ctx)
             }
-            Self::Or(left, right) => {
-                trace!("OR: executing left");
-                let mut left = left.execute().await;
+            Self::While(clause) => {
+                let clause = clause.clone();
+                let ctx = ctx.clone();
 
-                trace!("OR: waiting for left to finish");
-                let res = left.child.wait().await;
+                VashProcess::adhoc_process(move |mut child| async move {
+                    let _guard = ctx.enter_loop();
+                    let mut status = BuiltinExitStatus::new_success().into();
 
-                trace!("OR: left finished, checking exit status");
-                match res {
-                    Ok(exit) if exit.success() => left,
-                    Ok(_) => right.execute().await,
-                    Err(_) => left,
-                }
+                    loop {
+                        trace!("WHILE: checking {:?}", clause.condition);
+                        let condition = clause.condition.run_in(&ctx, &mut child).await;
+
+                        if loop_finished(&ctx) || condition.success() == clause.until {
+                            break;
+                        }
+
+                        status = clause.body.run_in(&ctx, &mut child).await;
+
+                        if loop_finished(&ctx) {
+                            break;
+                        }
+                    }
+
+                    status
+                })
             }
-            Self::Pipe(left, right) => {
-                trace!("spawning right side of pipe");
-                let mut right = right.execute().await;
-                trace!("spawning left side of pipe");
-                let mut left = left.execute().await;
-
-                trace!("spawning pipe thread");
-                tokio::task::spawn(async move {
-                    tokio::io::copy(&mut left.stdout, &mut right.stdin)
-                        .await
-                        .unwrap();
-                    trace!("pipe thread finished");
-                });
+            Self::For(clause) => {
+                let mut expander = Expander::new(ctx);
 
-                VashProcess {
-                    stdin: left.stdin,
-                    stdout: right.stdout,
-                    stderr: right.stderr,
-                    child: right.child,
-                }
+                let values = match &clause.words {
+                    Some(words) => match expander.expand_words(words).await {
+                        Ok(values) => values,
+                        Err(err) => {
+                            return expander
+                                .finish(VashProcess::failure(format!("vash: {}\n", err)))
+                        }
+                    },
+                    None => ctx.positional(),
+                };
+
+                let clause = clause.clone();
+                let ctx = ctx.clone();
+
+                expander.finish(VashProcess::adhoc_process(|mut child| async move {
+                    let _guard = ctx.enter_loop();
+                    let mut status = BuiltinExitStatus::new_success().into();
+
+                    for value in values {
+                        trace!("FOR: {} = {:?}", clause.variable, value);
+                        ctx.set_var(&clause.variable, value);
+                        status = clause.body.run_in(&ctx, &mut child).await;
+
+                        if loop_finished(&ctx) {
+                            break;
+                        }
+                    }
+
+                    status
+                }))
             }
-            Self::RedirectPipe(left, dest) => {
-                let left = left.execute().await;
+            Self::Case(clause) => {
+                let mut expander = Expander::new(ctx);
 
-                let _from: Box<dyn AsyncRead> = match &dest.from {
-                    PipeType::Stdout => Box::new(left.stdout),
-                    PipeType::Stderr => Box::new(left.stderr),
-                    PipeType::File(path) => {
-                        let file = tokio::fs::File::create(path).await.unwrap();
-                        Box::new(file)
+                let word = match expander.expand_word(&clause.word).await {
+                    Ok(word) => word,
+                    Err(err) => {
+                        return expander.finish(VashProcess::failure(format!("vash: {}\n", err)))
                     }
-                    _ => unreachable!("cannot pipe from null or stdin"),
                 };
 
-                let _to: Box<dyn AsyncWrite> = match &dest.to {
-                    PipeType::Null => Box::new(tokio::io::sink()),
-                    PipeType::Stdin => Box::new(left.stdin),
-                    PipeType::File(path) => {
-                        let file = tokio::fs::File::create(path).await.unwrap();
-                        Box::new(file)
+                let mut items = Vec::new();
+
+                for item in &clause.items {
+                    let mut patterns = Vec::new();
+
+                    for pattern in &item.patterns {
+                        match expander.expand_pattern(pattern).await {
+                            Ok(pattern) => patterns.push(Pattern::new(&pattern)),
+                            Err(err) => {
+                                return expander
+                                    .finish(VashProcess::failure(format!("vash: {}\n", err)))
+                            }
+                        }
+                    }
+
+                    items.push((patterns, item.body.clone(), item.terminator));
+                }
+
+                let ctx = ctx.clone();
+
+                expander.finish(VashProcess::adhoc_process(|mut child| async move {
+                    let mut status = BuiltinExitStatus::new_success().into();
+                    // whether the last body ended with `;&`
+                    let mut fall_through = false;
+
+                    for (patterns, body, terminator) in &items {
+                        if !fall_through && !patterns.iter().any(|pattern| pattern.matches(&word)) {
+                            continue;
+                        }
+
+                        trace!("CASE: running {:?}", body);
+                        status = body.run_in(&ctx, &mut child).await;
+
+                        if ctx.control_pending() {
+                            break;
+                        }
+
+                        match terminator {
+                            CaseTerminator::Break => break,
+                            CaseTerminator::FallThrough => fall_through = true,
+                            CaseTerminator::Continue => fall_through = false,
+                        }
                     }
-                    _ => unreachable!("cannot pipe to stdout or stderr"),
+
+                    status
+                }))
+            }
+            Self::Arithmetic(command) => {
+                let mut expander = Expander::new(ctx);
+
+                let process = match expander.expand_arithmetic(&command.expression).await {
+                    Ok(0) => VashProcess::sink_failure(),
+                    Ok(_) => VashProcess::sink(),
+                    Err(err) => VashProcess::failure(format!("vash: {}\n", err)),
                 };
 
-                // need to update execution context to use an abstraction over
-                // these streams so I can replace some with a sink, union, etc.
-                unimplemented!("redirect pipe");
+                expander.finish(process)
+            }
+            Self::BraceGroup(group) => group.body.execute(ctx).await,
+            Self::Subshell(group) => {
+                trace!("SUBSHELL: executing {:?}", group.body);
+                group.body.execute(&ctx.fork()).await
+            }
+            Self::Function(definition) => {
+                ctx.define_function(&definition.name, (*definition.body).clone());
+                VashProcess::sink()
+            }
+            Self::Background(command) => {
+                trace!("BACKGROUND: starting {:?}", command.plan);
+                // like a subshell, the job gets its own copy of the shell
+                let mut process = command.plan.execute(&ctx.fork()).await;
+
+                // nothing can type into a background job, so it sees the end
+                // of its input straight away
+                drop(std::mem::replace(
+                    &mut process.stdin,
+                    VashWrite::Sink(tokio::io::sink()),
+                ));
+
+                ctx.spawn_job(BackgroundJob {
+                    process,
+                    command: command.source.clone(),
+                });
+
+                VashProcess::sink()
+            }
+            Self::RedirectPipe(plan, redirection) => redirection.apply(plan, ctx).await,
+            Self::NoOp => VashProcess::sink(),
+        }
+    }
+
+    /// The commands of the pipeline this plan is, from first to last, or just
+    /// the plan itself if it isn't one.
+    fn pipeline_stages(&self) -> Vec<&ExecutionPlan> {
+        match self {
+            Self::Pipe(left, right) => {
+                let mut stages = left.pipeline_stages();
+                stages.push(right);
+                stages
+            }
+            plan => vec![plan],
+        }
+    }
+
+    /// Evaluates this plan from inside of the process it returns rather than
+    /// up front, so that whatever starts it doesn't have to wait for any of
+    /// its commands to finish.
+    pub fn execute_lazily(self, ctx: &ShellContext) -> VashProcess {
+        let ctx = ctx.clone();
+
+        VashProcess::adhoc_process(|mut child| async move {
+            self.execute(&ctx).await.forward(&mut child).await
+        })
+    }
+
+    /// Runs this plan to completion as one stage of the composite process
+    /// `child`, recording its exit status for `$?`.
+    async fn run_in(&self, ctx: &ShellContext, child: &mut PseudoChild) -> VashExitStatus {
+        let status = self.execute(ctx).await.forward(child).await;
+        ctx.set_last_status(status.status_code());
+        status
+    }
+}
+
+/// Handles any `break` or `continue` run by the body of a loop, returning
+/// whether the loop should stop. Whatever is left of a `break N` or
+/// `continue N` is passed on to the loops further out, as is a `return` or
+/// an `exit`.
+fn loop_finished(ctx: &ShellContext) -> bool {
+    match ctx.take_control() {
+        Some(control @ (Control::Return(_) | Control::Exit(_))) => {
+            ctx.set_control(control);
+            true
+        }
+        Some(Control::Break(levels)) => {
+            if levels > 1 {
+                ctx.set_control(Control::Break(levels - 1));
             }
-            _ => unimplemented!(),
+            true
+        }
+        Some(Control::Continue(levels)) if levels > 1 => {
+            ctx.set_control(Control::Continue(levels - 1));
+            true
+        }
+        Some(Control::Continue(_)) | None => false,
+    }
+}
+
+/// Runs the body of a function with `args` as its positional parameters and
+/// `assigned` as temporary variables.
+fn call(
+    function: Arc<ExecutionPlan>,
+    ctx: &ShellContext,
+    args: Vec<String>,
+    assigned: Vec<(String, String)>,
+) -> VashProcess {
+    let ctx = ctx.clone();
+
+    VashProcess::adhoc_process(|mut child| async move {
+        let _assigned = ctx.assign_temporarily(assigned);
+        let _guard = ctx.enter_function(args);
+        let status = function.run_in(&ctx, &mut child).await;
+
+        match ctx.take_control() {
+            Some(Control::Return(status)) => BuiltinExitStatus::from_code(status).into(),
+            // a `break` or `continue` carries on to the loops the function
+            // was called from
+            Some(control) => {
+                ctx.set_control(control);
+                status
+            }
+            None => status,
+        }
+    })
+}
+
+impl SimpleCommand {
+    pub async fn execute(&self, ctx: &ShellContext) -> VashProcess {
+        let mut expander = Expander::new(ctx);
+
+        let argv = match expander.expand_words(&self.words).await {
+            Ok(argv) => argv,
+            Err(err) => return expander.finish(VashProcess::failure(format!("vash: {}\n", err))),
+        };
+
+        if argv.is_empty() {
+            for assignment in &self.assignments {
+                match expander.expand_word(&assignment.value).await {
+                    Ok(value) => ctx.set_var(&assignment.name, value),
+                    Err(err) => {
+                        return expander.finish(VashProcess::failure(format!("vash: {}\n", err)))
+                    }
+                }
+            }
+
+            // an assignment takes on the status of its last command
+            // substitution, if it had any
+            let status = match expander.status() {
+                Some(status) => BuiltinExitStatus::from_code(status.status_code()),
+                None => BuiltinExitStatus::new_success(),
+            };
+
+            return expander.finish(VashProcess {
+                child: VashChild::PreExecuted(status),
+                ..VashProcess::sink()
+            });
+        }
+
+        // assignments before a command only apply to that command, but each
+        // one can already see the ones before it
+        let mut assigned = Vec::new();
+        let mut expanding = ctx.assign_temporarily(Vec::new());
+
+        for assignment in &self.assignments {
+            match expander.expand_word(&assignment.value).await {
+                Ok(value) => {
+                    expanding.assign(assignment.name.clone(), value.clone());
+                    assigned.push((assignment.name.clone(), value));
+                }
+                Err(err) => {
+                    return expander.finish(VashProcess::failure(format!("vash: {}\n", err)))
+                }
+            }
+        }
+
+        drop(expanding);
+
+        let (name, args) = (&argv[0], &argv[1..]);
+
+        if let Some(builtin) = BuiltinCommands::from_name(name) {
+            let _assigned = ctx.assign_temporarily(assigned);
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            return expander.finish(builtin.execute(ctx, &args).await);
+        }
+
+        if let Some(function) = ctx.function(name) {
+            return expander.finish(call(function, ctx, args.to_vec(), assigned));
+        }
+
+        let mut env = ctx.env();
+        env.extend(assigned);
+
+        expander.finish(spawn(ctx, name, args, env))
+    }
+}
+
+/// Starts the program `name`, with `env` as its entire environment. Later
+/// entries in `env` take precedence over earlier ones with the same name.
+fn spawn<S>(ctx: &ShellContext, name: &str, args: &[S], env: Vec<(String, String)>) -> VashProcess
+where
+    S: AsRef<OsStr>,
+{
+    let mut cmd = Command::new(name);
+
+    cmd.args(args)
+        .env_clear()
+        .envs(env)
+        .current_dir(ctx.cwd())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    trace!("spawning command: {:?}", cmd);
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            let (message, status) = match err.kind() {
+                io::ErrorKind::NotFound => ("command not found".to_owned(), 127),
+                io::ErrorKind::PermissionDenied => ("Permission denied".to_owned(), 126),
+                _ => (err.to_string(), 126),
+            };
+
+            return VashProcess {
+                child: VashChild::PreExecuted(BuiltinExitStatus::from_code(status)),
+                ..VashProcess::failure(format!("vash: {}: {}\n", name, message))
+            };
         }
+    };
+
+    let stdin = child.stdin.take().unwrap().into();
+    let stdout = child.stdout.take().unwrap().into();
+    let stderr = child.stderr.take().unwrap().into();
+
+    VashProcess {
+        stdin,
+        stdout,
+        stderr,
+        child: child.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn case_runs_the_first_matching_item() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "case b in a) echo 1;; b|c) echo 2;; b) echo 3;; esac").await,
+            "2\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "case z in x | y | z) echo hit;; *) echo miss;; esac").await,
+            "hit\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "case x in x) echo esac;; esac; echo esac").await,
+            "esac\nesac\n"
+        );
+
+        assert_eq!(run(&ctx, "case x in y) false;; esac").await.status, Some(0));
+        assert_eq!(run(&ctx, "case x in x) false;; esac").await.status, Some(1));
+    }
+
+    #[tokio::test]
+    async fn case_terminators() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                "case a in a) echo 1;& b) echo 2;& c) echo 3;; d) echo 4;; esac"
+            )
+            .await,
+            "1\n2\n3\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "case ab in a*) echo 1;;& c) echo 2;;& *b) echo 3;; *) echo 4;; esac"
+            )
+            .await,
+            "1\n3\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn quoted_case_patterns_match_literally() {
+        let ctx = ShellContext::from_env();
+        ctx.set_var("pattern", "a*");
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"case ab in "a*") echo 1;; a\*) echo 2;; *) echo 3;; esac"#
+            )
+            .await,
+            "3\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "case 'a*' in 'a*') echo 1;; esac").await,
+            "1\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"case ab in "$pattern") echo 1;; $pattern) echo 2;; esac"#
+            )
+            .await,
+            "2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn subshells_leave_the_shell_alone() {
+        let ctx = ShellContext::from_env();
+        let cwd = ctx.cwd();
+        ctx.set_var("x", "0");
+
+        let output = run(&ctx, "( cd /; x=1; echo $x $PWD ); echo $x").await;
+        assert_eq!(output.stdout, "1 /\n0\n");
+        assert_eq!(ctx.cwd(), cwd);
+        assert_eq!(ctx.var("x").as_deref(), Some("0"));
+
+        assert_eq!(stdout(&ctx, "( exit 3 ); echo $?").await, "3\n");
+    }
+
+    #[tokio::test]
+    async fn groups_run_in_the_shell() {
+        let ctx = ShellContext::from_env();
+        ctx.set_var("x", "0");
+
+        assert_eq!(stdout(&ctx, "{ cd /; x=1; }; echo $x").await, "1\n");
+        assert_eq!(ctx.cwd(), std::path::Path::new("/"));
+        assert_eq!(ctx.var("x").as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn groups_and_subshells_are_pipeline_stages() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "{ echo a; echo b; } | wc -l").await.trim(),
+            "2"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                "printf 'a\\nb\\n' | { head -n 1; echo c; } | tr a-z A-Z"
+            )
+            .await,
+            "A\nC\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "( echo a; echo b ) | ( cat; echo c )").await,
+            "a\nb\nc\n"
+        );
     }
 }
@@ -1,26 +1,201 @@
-#[derive(Debug)]
+use crate::parse::{word::Word, Span};
+
+#[derive(Debug, Clone)]
 pub enum ExecutionPlan {
-    Execute(String, Vec<String>),
+    Execute(SimpleCommand),
     Pipe(Box<ExecutionPlan>, Box<ExecutionPlan>),
     And(Box<ExecutionPlan>, Box<ExecutionPlan>),
     Or(Box<ExecutionPlan>, Box<ExecutionPlan>),
-    Background(Box<ExecutionPlan>),
+    Sequence(Vec<ExecutionPlan>),
+    Background(BackgroundCommand),
     RedirectPipe(Box<ExecutionPlan>, PipeRedirection),
+    If(IfClause),
+    While(WhileClause),
+    For(ForClause),
+    Case(CaseClause),
+    Function(FunctionDefinition),
+    Arithmetic(ArithmeticCommand),
+    /// `{ list; }`, which runs in the current shell.
+    BraceGroup(CommandGroup),
+    /// `( list )`, which runs in a copy of the shell, so any changes it makes
+    /// to variables or the working directory are thrown away afterwards.
+    Subshell(CommandGroup),
     NoOp,
 }
 
-#[derive(Debug)]
+impl ExecutionPlan {
+    /// The region of the source this plan was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Execute(cmd) => cmd.span.clone(),
+            Self::Pipe(left, right) | Self::And(left, right) | Self::Or(left, right) => {
+                left.span().start..right.span().end
+            }
+            Self::Sequence(plans) => match (plans.first(), plans.last()) {
+                (Some(first), Some(last)) => first.span().start..last.span().end,
+                _ => 0..0,
+            },
+            Self::Background(command) => command.span.clone(),
+            Self::RedirectPipe(plan, redirection) => match plan.as_ref() {
+                Self::NoOp => redirection.span.clone(),
+                plan => {
+                    let span = plan.span();
+                    span.start.min(redirection.span.start)..span.end.max(redirection.span.end)
+                }
+            },
+            Self::If(clause) => clause.span.clone(),
+            Self::While(clause) => clause.span.clone(),
+            Self::For(clause) => clause.span.clone(),
+            Self::Case(clause) => clause.span.clone(),
+            Self::Function(definition) => definition.span.clone(),
+            Self::Arithmetic(command) => command.span.clone(),
+            Self::BraceGroup(group) | Self::Subshell(group) => group.span.clone(),
+            Self::NoOp => 0..0,
+        }
+    }
+}
+
+/// A program (or builtin) name followed by its arguments, or a list of
+/// variable assignments.
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    /// Assignments to variables in the shell, or if there are words, to the
+    /// environment of only this command.
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub span: Span,
+}
+
+/// `if ...; then ...; elif ...; then ...; else ...; fi`
+#[derive(Debug, Clone)]
+pub struct IfClause {
+    /// Each condition along with the body that runs if it succeeds, tried in
+    /// order until one does.
+    pub branches: Vec<(ExecutionPlan, ExecutionPlan)>,
+    /// The `else` body, run if no condition succeeded.
+    pub otherwise: Option<Box<ExecutionPlan>>,
+    pub span: Span,
+}
+
+/// `while ...; do ...; done` or `until ...; do ...; done`
+#[derive(Debug, Clone)]
+pub struct WhileClause {
+    pub condition: Box<ExecutionPlan>,
+    pub body: Box<ExecutionPlan>,
+    /// Whether this is an `until` loop, which runs until the condition
+    /// succeeds rather than while it does.
+    pub until: bool,
+    pub span: Span,
+}
+
+/// `for NAME in ...; do ...; done`
+#[derive(Debug, Clone)]
+pub struct ForClause {
+    pub variable: String,
+    /// The words after `in`, or `None` to loop over the positional
+    /// parameters.
+    pub words: Option<Vec<Word>>,
+    pub body: Box<ExecutionPlan>,
+    pub span: Span,
+}
+
+/// `case WORD in PATTERN) ...;; esac`
+#[derive(Debug, Clone)]
+pub struct CaseClause {
+    pub word: Word,
+    pub items: Vec<CaseItem>,
+    pub span: Span,
+}
+
+/// `PATTERN | PATTERN) list ;;`
+#[derive(Debug, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: ExecutionPlan,
+    pub terminator: CaseTerminator,
+}
+
+/// What happens after the body of a matching case item has run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseTerminator {
+    /// `;;`, which ends the case.
+    Break,
+    /// `;&`, which runs the next item's body without checking its patterns.
+    FallThrough,
+    /// `;;&`, which carries on checking the patterns of the items after it.
+    Continue,
+}
+
+/// `function NAME { ... }` or `NAME() { ... }`
+#[derive(Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: Box<ExecutionPlan>,
+    pub span: Span,
+}
+
+/// `(( expression ))`, which succeeds if the expression isn't 0.
+#[derive(Debug, Clone)]
+pub struct ArithmeticCommand {
+    pub expression: String,
+    pub span: Span,
+}
+
+/// `command &`, which runs `command` as a job alongside whatever runs after
+/// it.
+#[derive(Debug, Clone)]
+pub struct BackgroundCommand {
+    pub plan: Box<ExecutionPlan>,
+    /// The command as it was written, for showing in the job table.
+    pub source: String,
+    pub span: Span,
+}
+
+/// A list of commands run as if they were one command.
+#[derive(Debug, Clone)]
+pub struct CommandGroup {
+    pub body: Box<ExecutionPlan>,
+    pub span: Span,
+}
+
+/// `NAME=value`
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+    pub span: Span,
+}
+
+/// Connects one of a process' streams to a file or another stream.
+///
+/// Multiple redirections on one command are nested with the first one
+/// outermost, so each redirection sees the streams as left by the ones
+/// before it (`> out 2>&1` sends both streams to `out`, while `2>&1 > out`
+/// sends only stdout there).
+#[derive(Debug, Clone)]
 pub struct PipeRedirection {
     pub from: PipeType,
     pub to: PipeType,
     pub append: bool,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PipeType {
     Stdout,
     Stderr,
     Stdin,
     Null,
-    File(String),
+    File(Word),
+    HereDoc(HereDoc),
+    HereString(Word),
+}
+
+/// The body of a `<<` heredoc.
+#[derive(Debug, Clone)]
+pub struct HereDoc {
+    pub body: String,
+    /// Whether parameters and commands in the body are expanded, which is
+    /// only the case when no part of the delimiter was quoted.
+    pub expand: bool,
 }
@@ -1,3 +1,59 @@
 pub mod delegate;
 pub mod execute;
 pub mod execution_plan;
+pub mod pipeline;
+pub mod redirect;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use crate::{context::ShellContext, parse::parse_command};
+
+    use super::delegate::{DelegateMessage, ExecutionDelegate};
+
+    /// What a command wrote out and the status it exited with.
+    #[derive(Debug, Default)]
+    pub struct Output {
+        pub stdout: String,
+        pub stderr: String,
+        pub status: Option<i32>,
+    }
+
+    /// Runs `source`, panicking if it doesn't finish within a few seconds.
+    pub async fn run(ctx: &ShellContext, source: &str) -> Output {
+        let process = parse_command(source).unwrap().execute(ctx).await;
+        let mut delegate = ExecutionDelegate::spawn(process).await;
+        let mut output = Output::default();
+
+        let finished = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match delegate.rx.recv().await {
+                    Some(DelegateMessage::Stdout(data)) => {
+                        output.stdout.push_str(&String::from_utf8_lossy(&data))
+                    }
+                    Some(DelegateMessage::Stderr(data)) => {
+                        output.stderr.push_str(&String::from_utf8_lossy(&data))
+                    }
+                    Some(DelegateMessage::Exit(status)) => break Some(status.status_code()),
+                    Some(DelegateMessage::Error(err)) => panic!("{}", err),
+                    None => break None,
+                }
+            }
+        })
+        .await;
+
+        match finished {
+            Ok(status) => {
+                ctx.set_last_status(status.unwrap_or(1));
+                Output { status, ..output }
+            }
+            Err(_) => panic!("{:?} didn't finish", source),
+        }
+    }
+
+    /// Runs `source`, returning what it wrote to stdout.
+    pub async fn stdout(ctx: &ShellContext, source: &str) -> String {
+        run(ctx, source).await.stdout
+    }
+}
@@ -0,0 +1,222 @@
+use itertools::Itertools;
+use nix::sys::signal::Signal;
+use tokio::{io::AsyncWriteExt, select, task::JoinHandle};
+
+use crate::{
+    context::ShellContext,
+    process::{
+        child::{Running, VashChild},
+        read::{ReadMerge, VashRead},
+        status::{BuiltinExitStatus, VashExitStatus},
+        write::VashWrite,
+        VashProcess,
+    },
+};
+
+/// The status of a stage that was stopped for writing to a stage that had
+/// already stopped reading, as if it was killed by `SIGPIPE`.
+const BROKEN_PIPE: i32 = 128 + Signal::SIGPIPE as i32;
+
+/// The commands of a pipeline, each of which has its stdout connected to the
+/// stdin of the next.
+pub struct Pipeline {
+    stages: Vec<VashProcess>,
+}
+
+impl Pipeline {
+    /// `stages` are in the order they were written in, and there must be at
+    /// least one of them.
+    pub fn new(stages: Vec<VashProcess>) -> Self {
+        assert!(!stages.is_empty(), "a pipeline needs at least one stage");
+
+        Self { stages }
+    }
+
+    /// Connects the stages together into one process, which reads into the
+    /// first stage, writes out whatever the last stage does and merges the
+    /// errors of all of them. It finishes once every stage has, with the
+    /// status of the last stage or, with `set -o pipefail`, of the last one to
+    /// fail. The status of each stage is left in `PIPESTATUS`.
+    pub fn into_process(self, ctx: &ShellContext) -> VashProcess {
+        let mut stdin = None;
+        let mut stdout = None;
+        let mut stderrs = Vec::new();
+        let mut children = Vec::new();
+        let mut pipes = Vec::new();
+
+        for stage in self.stages {
+            match stdout.take() {
+                Some(previous) => {
+                    pipes.push(Some(tokio::task::spawn(connect(previous, stage.stdin))))
+                }
+                None => stdin = Some(stage.stdin),
+            }
+
+            stdout = Some(stage.stdout);
+            stderrs.push(stage.stderr);
+            children.push(stage.child);
+        }
+
+        // the last stage writes straight out of the pipeline
+        pipes.push(None);
+
+        // every stage is waited for at once, since any of them could be the
+        // one holding up the others. each wait is a child of its own so that
+        // dropping the pipeline still kills whatever is running
+        let running = Running::default();
+
+        let stages = children
+            .into_iter()
+            .zip(pipes)
+            .map(|(child, pipe)| {
+                let guard = running.add(&child);
+                let handle = tokio::task::spawn(async move {
+                    let _guard = guard;
+                    wait(child, pipe).await
+                });
+
+                VashChild::Thread(handle, Running::default())
+            })
+            .collect::<Vec<_>>();
+
+        let ctx = ctx.clone();
+
+        let supervisor = tokio::task::spawn(async move {
+            let mut statuses = Vec::with_capacity(stages.len());
+
+            for mut stage in stages {
+                let status = match stage.wait().await {
+                    Ok(status) => status.status_code(),
+                    Err(_) => 1,
+                };
+
+                statuses.push(status);
+            }
+
+            trace!("PIPELINE: finished with {:?}", statuses);
+            ctx.set_var("PIPESTATUS", statuses.iter().join(" "));
+
+            let status = if ctx.options().pipefail {
+                statuses.iter().rev().find(|status| **status != 0)
+            } else {
+                statuses.last()
+            };
+
+            BuiltinExitStatus::from_code(status.copied().unwrap_or(0)).into()
+        });
+
+        VashProcess {
+            stdin: stdin.unwrap(),
+            stdout: stdout.unwrap(),
+            stderr: VashRead::Merged(ReadMerge::new(stderrs)),
+            child: VashChild::Thread(supervisor, running),
+        }
+    }
+}
+
+/// Copies the output of one stage into the input of the next until either
+/// side closes, returning `true` if it was the next stage that stopped
+/// reading.
+async fn connect(mut from: VashRead, mut to: VashWrite) -> bool {
+    let broken = match tokio::io::copy(&mut from, &mut to).await {
+        Ok(_) => false,
+        Err(err) => {
+            trace!("PIPELINE: stopped copying: {}", err);
+            true
+        }
+    };
+
+    to.shutdown().await.ok();
+
+    broken
+}
+
+/// Waits for one stage to finish. `pipe` is the copy of its output into the
+/// next stage, if there is one.
+///
+/// A real process finds out that the next stage stopped reading from the
+/// pipe being closed, but nothing tells an adhoc process that its output
+/// is going nowhere, so it is stopped instead.
+async fn wait(mut child: VashChild, pipe: Option<JoinHandle<bool>>) -> VashExitStatus {
+    if let Some(pipe) = pipe {
+        select! {
+            status = child.wait() => {
+                return status.unwrap_or_else(|_| BuiltinExitStatus::new_failure().into());
+            }
+            Ok(true) = pipe => {}
+        }
+
+        if let VashChild::Thread(..) = child {
+            trace!("PIPELINE: stopping a stage whose output is going nowhere");
+            child.kill().await.ok();
+            return BuiltinExitStatus::from_code(BROKEN_PIPE).into();
+        }
+    }
+
+    child
+        .wait()
+        .await
+        .unwrap_or_else(|_| BuiltinExitStatus::new_failure().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::tests::run;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stages_writing_to_a_closed_pipe_are_stopped() {
+        let ctx = ShellContext::from_env();
+
+        for source in [
+            "{ while true; do echo x; done; } | head -n 1",
+            "while true; do echo x; done | true",
+            "yes | head -n 1",
+            "{ while true; do echo x; done; } | cat | head -n 1",
+        ] {
+            let stdout = run(&ctx, source).await.stdout;
+            assert!(
+                matches!(stdout.as_str(), "x\n" | "y\n" | ""),
+                "{:?}",
+                stdout
+            );
+
+            let statuses = ctx.var("PIPESTATUS").unwrap();
+            assert!(
+                statuses.starts_with(&BROKEN_PIPE.to_string()),
+                "{:?}: {}",
+                source,
+                statuses
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn every_stage_is_reaped() {
+        let ctx = ShellContext::from_env();
+
+        let output = run(&ctx, "echo a | false | cat").await;
+        assert_eq!((output.stdout.as_str(), output.status), ("", Some(0)));
+        assert_eq!(ctx.var("PIPESTATUS").as_deref(), Some("0 1 0"));
+
+        let output = run(&ctx, "printf 'b\\na\\n' | sort | head -n 1").await;
+        assert_eq!((output.stdout.as_str(), output.status), ("a\n", Some(0)));
+        assert_eq!(ctx.var("PIPESTATUS").as_deref(), Some("0 0 0"));
+    }
+
+    #[tokio::test]
+    async fn pipefail_reports_the_last_failure() {
+        let ctx = ShellContext::from_env();
+        ctx.set_option("pipefail", true);
+
+        assert_eq!(run(&ctx, "false | true").await.status, Some(1));
+        assert_eq!(
+            run(&ctx, "true | sh -c 'exit 3' | false | true")
+                .await
+                .status,
+            Some(1)
+        );
+        assert_eq!(run(&ctx, "true | true").await.status, Some(0));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{io, path::PathBuf};
+
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    task::JoinHandle,
+};
+
+use super::execution_plan::{ExecutionPlan, PipeRedirection, PipeType};
+use crate::{
+    context::ShellContext,
+    expand::Expander,
+    process::{
+        child::{Running, VashChild},
+        read::{ReadMerge, ReadSink, VashRead},
+        status::BuiltinExitStatus,
+        write::VashWrite,
+        VashProcess,
+    },
+};
+
+impl PipeRedirection {
+    /// Executes `plan` with this redirection applied to it.
+    ///
+    /// Files are opened before the plan is executed, so a redirection that
+    /// cannot be opened prevents the command from running at all.
+    pub async fn apply(&self, plan: &ExecutionPlan, ctx: &ShellContext) -> VashProcess {
+        let mut expander = Expander::new(ctx);
+
+        // the target of the redirection, or the text fed to stdin
+        let operand = match (&self.from, &self.to) {
+            (PipeType::File(word) | PipeType::HereString(word), _) | (_, PipeType::File(word)) => {
+                expander.expand_word(word).await.map(Some)
+            }
+            (PipeType::HereDoc(heredoc), _) if heredoc.expand => {
+                expander.expand_heredoc(&heredoc.body).await.map(Some)
+            }
+            (PipeType::HereDoc(heredoc), _) => Ok(Some(heredoc.body.clone())),
+            _ => Ok(None),
+        };
+
+        let operand = match operand {
+            Ok(operand) => operand,
+            Err(err) => return expander.finish(VashProcess::failure(format!("vash: {}\n", err))),
+        };
+
+        let file = match self
+            .open(operand.as_deref().map(|path| ctx.resolve(path)))
+            .await
+        {
+            Ok(file) => file,
+            Err(err) => {
+                return expander.finish(VashProcess::failure(format!(
+                    "vash: {}: {}\n",
+                    operand.unwrap_or_default(),
+                    err
+                )));
+            }
+        };
+
+        let mut process = plan.execute(ctx).await;
+
+        match (&self.from, &self.to, file) {
+            (PipeType::Stdout | PipeType::Stderr, PipeType::File(_) | PipeType::Null, file) => {
+                let stream = match self.from {
+                    PipeType::Stdout => &mut process.stdout,
+                    _ => &mut process.stderr,
+                };
+                let mut stream = std::mem::replace(stream, VashRead::Sink(ReadSink));
+
+                let mut writer: Box<dyn AsyncWrite + Send + Unpin> = match file {
+                    Some(file) => Box::new(file),
+                    None => Box::new(tokio::io::sink()),
+                };
+
+                let copy = tokio::task::spawn(async move {
+                    tokio::io::copy(&mut stream, &mut writer).await.ok();
+                    writer.shutdown().await.ok();
+                });
+
+                process.child = wait_for(process.child, copy);
+            }
+            (
+                PipeType::File(_) | PipeType::HereDoc(_) | PipeType::HereString(_),
+                PipeType::Stdin,
+                file,
+            ) => {
+                let operand = operand.unwrap_or_default();
+                let mut source: Box<dyn AsyncRead + Send + Unpin> = match (&self.from, file) {
+                    (_, Some(file)) => Box::new(file),
+                    (PipeType::HereString(_), _) => {
+                        Box::new(VashRead::Canned(format!("{}\n", operand).into_bytes()))
+                    }
+                    _ => Box::new(VashRead::Canned(operand.into_bytes())),
+                };
+
+                let mut stdin =
+                    std::mem::replace(&mut process.stdin, VashWrite::Sink(tokio::io::sink()));
+
+                tokio::task::spawn(async move {
+                    tokio::io::copy(&mut source, &mut stdin).await.ok();
+                    stdin.shutdown().await.ok();
+                });
+            }
+            (PipeType::Stderr, PipeType::Stdout, _) => {
+                let stdout = std::mem::replace(&mut process.stdout, VashRead::Sink(ReadSink));
+                let stderr = std::mem::replace(&mut process.stderr, VashRead::Sink(ReadSink));
+                process.stdout = VashRead::Merged(ReadMerge::new([stdout, stderr]));
+            }
+            (PipeType::Stdout, PipeType::Stderr, _) => {
+                let stdout = std::mem::replace(&mut process.stdout, VashRead::Sink(ReadSink));
+                let stderr = std::mem::replace(&mut process.stderr, VashRead::Sink(ReadSink));
+                process.stderr = VashRead::Merged(ReadMerge::new([stderr, stdout]));
+            }
+            (from, to, _) => unreachable!("cannot redirect {:?} to {:?}", from, to),
+        }
+
+        expander.finish(process)
+    }
+
+    /// Opens the file this redirection reads from or writes to, which is at
+    /// `path` once expanded and resolved.
+    async fn open(&self, path: Option<PathBuf>) -> io::Result<Option<File>> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+
+        match (&self.from, &self.to) {
+            (PipeType::File(_), _) => File::open(path).await.map(Some),
+            (_, PipeType::File(_)) => OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(self.append)
+                .truncate(!self.append)
+                .open(path)
+                .await
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Makes waiting on `child` also wait for `copy`, so that everything written
+/// to a redirected stream has landed by the time the process is done.
+fn wait_for(mut child: VashChild, copy: JoinHandle<()>) -> VashChild {
+    let running = Running::default();
+    let guard = running.add(&child);
+
+    let handle = tokio::task::spawn(async move {
+        let _guard = guard;
+        let status = child
+            .wait()
+            .await
+            .unwrap_or_else(|_| BuiltinExitStatus::new_failure().into());
+
+        copy.await.ok();
+
+        status
+    });
+
+    VashChild::Thread(handle, running)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+        parse::{parse_command, ParseErrorKind},
+    };
+
+    /// A context whose working directory is a fresh, empty directory.
+    fn context(name: &str) -> ShellContext {
+        let dir = std::env::temp_dir().join(format!("vash-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let ctx = ShellContext::from_env();
+        ctx.set_cwd(dir);
+        ctx
+    }
+
+    #[tokio::test]
+    async fn redirections_apply_from_left_to_right() {
+        let ctx = context("redirect-order");
+        let both = "sh -c 'echo out; echo err >&2'";
+
+        let output = run(&ctx, &format!("{} > f 2>&1; cat f", both)).await;
+        assert_eq!(output.stdout, "out\nerr\n");
+        assert_eq!(output.stderr, "");
+
+        let output = run(&ctx, &format!("{} 2>&1 > g; cat g", both)).await;
+        assert_eq!(output.stdout, "err\nout\n");
+        assert_eq!(output.stderr, "");
+
+        let output = run(&ctx, "sh -c 'echo out' 1>&2").await;
+        assert_eq!(
+            (output.stdout.as_str(), output.stderr.as_str()),
+            ("", "out\n")
+        );
+
+        std::fs::remove_dir_all(ctx.cwd()).ok();
+    }
+
+    #[tokio::test]
+    async fn appending_and_truncating() {
+        let ctx = context("redirect-append");
+
+        assert_eq!(
+            stdout(&ctx, "echo a > f; echo b >> f; cat f").await,
+            "a\nb\n"
+        );
+        assert_eq!(stdout(&ctx, "echo c > f; cat f").await, "c\n");
+        assert_eq!(stdout(&ctx, "echo d >> new; cat < new").await, "d\n");
+
+        std::fs::remove_dir_all(ctx.cwd()).ok();
+    }
+
+    #[tokio::test]
+    async fn files_that_cant_be_opened_stop_the_command() {
+        let ctx = context("redirect-missing");
+
+        let output = run(&ctx, "echo ran < missing").await;
+        assert_eq!(output.stdout, "");
+        assert!(
+            output.stderr.starts_with("vash: missing: "),
+            "{:?}",
+            output.stderr
+        );
+        assert_eq!(output.status, Some(1));
+
+        let output = run(&ctx, "echo ran > missing/f").await;
+        assert_eq!(output.stdout, "");
+        assert!(output.stderr.starts_with("vash: missing/f: "));
+        assert_eq!(output.status, Some(1));
+
+        assert_eq!(stdout(&ctx, "cat < missing; echo $?").await, "1\n");
+
+        std::fs::remove_dir_all(ctx.cwd()).ok();
+    }
+
+    #[test]
+    fn only_stdout_and_stderr_can_be_duplicated() {
+        for (source, fd) in [("echo x 3>&1", 3), ("echo x >&5", 5), ("echo x 2>&3", 3)] {
+            let err = parse_command(source).unwrap_err();
+            assert!(
+                matches!(err.kind, ParseErrorKind::UnsupportedDescriptor(n) if n == fd),
+                "{:?}: {:?}",
+                source,
+                err
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn heredocs_and_here_strings_feed_stdin() {
+        let ctx = ShellContext::from_env();
+        ctx.set_var("x", "1");
+
+        assert_eq!(
+            stdout(
+                &ctx,
+                "cat <<EOF\n$x \\$x \\\\ \\a $(echo y) \"q\" 'q'\nlong \\\nline\nEOF"
+            )
+            .await,
+            "1 $x \\ \\a y \"q\" 'q'\nlong line\n"
+        );
+        assert_eq!(stdout(&ctx, "cat <<'EOF'\n$x \\$x\nEOF").await, "$x \\$x\n");
+        assert_eq!(stdout(&ctx, "cat <<-EOF\n\t$x\n\tEOF").await, "1\n");
+        assert_eq!(stdout(&ctx, "cat <<A <<B\n1\nA\n2\nB").await, "2\n");
+        assert_eq!(stdout(&ctx, "cat <<A; cat <<B\n1\nA\n2\nB").await, "1\n2\n");
+
+        assert_eq!(stdout(&ctx, "cat <<< \"$x  y\"").await, "1  y\n");
+        assert_eq!(stdout(&ctx, "cat <<< $x").await, "1\n");
+    }
+}
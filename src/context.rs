@@ -0,0 +1,484 @@
+use std::{
+    collections::HashMap,
+    mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    cmd::{delegate::ExecutionDelegate, execution_plan::ExecutionPlan},
+    job::{BackgroundJob, JobRequest, JobTable},
+    prelude::*,
+};
+
+/// State shared by everything executing in the same shell, such as its
+/// variables and working directory. Cloning a context gives another handle to
+/// the same state.
+#[derive(Clone, Default)]
+pub struct ShellContext {
+    inner: Arc<Mutex<ContextInner>>,
+}
+
+#[derive(Clone, Default)]
+struct ContextInner {
+    variables: HashMap<String, Variable>,
+    last_status: i32,
+    cwd: PathBuf,
+    options: ShellOptions,
+    /// How many loops are currently running.
+    loop_depth: usize,
+    control: Option<Control>,
+    functions: HashMap<String, Arc<ExecutionPlan>>,
+    /// `$1`, `$2` and so on.
+    positional: Vec<String>,
+    /// The variables each running function made local with `local`, along
+    /// with the values they had before, innermost function last.
+    locals: Vec<HashMap<String, Option<Variable>>>,
+    /// Where commands started with `&`, and anything else that needs the
+    /// job table, are sent.
+    jobs: Option<Sender<JobRequest>>,
+    /// The process id of the last background job, as seen by `$!`.
+    last_background: Option<u32>,
+}
+
+/// A `break`, `continue` or `return` waiting for the loop or function it
+/// applies to to see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Break(usize),
+    Continue(usize),
+    /// Leaves the current function with the given status.
+    Return(i32),
+    /// Exits the shell with the given status.
+    Exit(i32),
+}
+
+/// Marks a loop as running until it is dropped, which also happens when the
+/// loop is cancelled.
+pub struct LoopGuard {
+    ctx: ShellContext,
+}
+
+impl Drop for LoopGuard {
+    fn drop(&mut self) {
+        let mut inner = self.ctx.lock();
+        inner.loop_depth -= 1;
+
+        if inner.loop_depth == 0
+            && !matches!(inner.control, Some(Control::Return(_) | Control::Exit(_)))
+        {
+            inner.control = None;
+        }
+    }
+}
+
+/// Gives a function call its own positional parameters and local variables
+/// until it is dropped, at which point the caller's are restored.
+pub struct FunctionGuard {
+    ctx: ShellContext,
+    positional: Vec<String>,
+}
+
+impl Drop for FunctionGuard {
+    fn drop(&mut self) {
+        let mut inner = self.ctx.lock();
+        inner.positional = mem::take(&mut self.positional);
+
+        for (name, variable) in inner.locals.pop().unwrap_or_default() {
+            match variable {
+                Some(variable) => inner.variables.insert(name, variable),
+                None => inner.variables.remove(&name),
+            };
+        }
+    }
+}
+
+/// Gives variables temporary values, like the `NAME=value` assignments
+/// before a command, until it is dropped, at which point the values they had
+/// before are restored.
+pub struct AssignmentGuard {
+    ctx: ShellContext,
+    saved: Vec<(String, Option<Variable>)>,
+}
+
+impl AssignmentGuard {
+    /// Gives one more variable a temporary value, which is restored along
+    /// with the rest.
+    pub fn assign(&mut self, name: String, value: String) {
+        let variable = Variable {
+            value,
+            exported: true,
+        };
+
+        let saved = self.ctx.lock().variables.insert(name.clone(), variable);
+        self.saved.push((name, saved));
+    }
+}
+
+impl Drop for AssignmentGuard {
+    fn drop(&mut self) {
+        let mut inner = self.ctx.lock();
+
+        for (name, variable) in self.saved.drain(..).rev() {
+            match variable {
+                Some(variable) => inner.variables.insert(name, variable),
+                None => inner.variables.remove(&name),
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: String,
+    /// Whether the variable is passed on to the environment of commands.
+    pub exported: bool,
+}
+
+/// The options that can be toggled with `shopt` or `set -o`.
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    /// Globs that match nothing expand to nothing instead of themselves.
+    pub nullglob: bool,
+    /// Globs that match nothing are an error.
+    pub failglob: bool,
+    /// A pipeline fails if any of its commands do, not just the last one.
+    pub pipefail: bool,
+}
+
+impl ShellOptions {
+    /// The names of the options toggled with `shopt`, in the order it lists
+    /// them.
+    pub const NAMES: &'static [&'static str] = &["failglob", "nullglob"];
+    /// The names of the options toggled with `set -o`, in the order it lists
+    /// them.
+    pub const SET_NAMES: &'static [&'static str] = &["pipefail"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "failglob" => Some(self.failglob),
+            "nullglob" => Some(self.nullglob),
+            "pipefail" => Some(self.pipefail),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "failglob" => Some(&mut self.failglob),
+            "nullglob" => Some(&mut self.nullglob),
+            "pipefail" => Some(&mut self.pipefail),
+            _ => None,
+        }
+    }
+}
+
+impl ShellContext {
+    /// Creates a context whose variables and working directory are taken
+    /// from the environment vash was started in.
+    pub fn from_env() -> Self {
+        let ctx = Self::default();
+
+        ctx.lock().cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+
+        ctx.lock().variables = std::env::vars()
+            .map(|(name, value)| {
+                (
+                    name,
+                    Variable {
+                        value,
+                        exported: true,
+                    },
+                )
+            })
+            .collect();
+
+        ctx
+    }
+
+    /// Creates a copy of this context that can be changed without affecting
+    /// the original, like the environment of a subshell.
+    pub fn fork(&self) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(self.lock().clone())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ContextInner> {
+        self.inner.lock().unwrap()
+    }
+
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.lock()
+            .variables
+            .get(name)
+            .map(|variable| variable.value.clone())
+    }
+
+    pub fn set_var(&self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+
+        self.lock()
+            .variables
+            .entry(name.to_owned())
+            .and_modify(|variable| variable.value.clone_from(&value))
+            .or_insert(Variable {
+                value,
+                exported: false,
+            });
+    }
+
+    pub fn unset_var(&self, name: &str) {
+        self.lock().variables.remove(name);
+    }
+
+    /// Marks `name` as exported, setting it to `value` if there is one.
+    /// Exporting a variable that isn't set does nothing.
+    pub fn export(&self, name: &str, value: Option<String>) {
+        let mut inner = self.lock();
+
+        match (inner.variables.get_mut(name), value) {
+            (Some(variable), value) => {
+                variable.exported = true;
+
+                if let Some(value) = value {
+                    variable.value = value;
+                }
+            }
+            (None, Some(value)) => {
+                inner.variables.insert(
+                    name.to_owned(),
+                    Variable {
+                        value,
+                        exported: true,
+                    },
+                );
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Stops passing `name` on to the environment of commands, without
+    /// unsetting it.
+    pub fn unexport(&self, name: &str) {
+        if let Some(variable) = self.lock().variables.get_mut(name) {
+            variable.exported = false;
+        }
+    }
+
+    /// The exported variables, which make up the environment commands are
+    /// run with, sorted by name.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let mut env = self
+            .lock()
+            .variables
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| (name.clone(), variable.value.clone()))
+            .collect::<Vec<_>>();
+
+        env.sort();
+
+        env
+    }
+
+    /// Sets each of `vars` as an exported variable until the returned guard
+    /// is dropped.
+    pub fn assign_temporarily(&self, vars: Vec<(String, String)>) -> AssignmentGuard {
+        let mut guard = AssignmentGuard {
+            ctx: self.clone(),
+            saved: Vec::new(),
+        };
+
+        for (name, value) in vars {
+            guard.assign(name, value);
+        }
+
+        guard
+    }
+
+    /// The exit status of the last command to finish, as seen by `$?`.
+    pub fn last_status(&self) -> i32 {
+        self.lock().last_status
+    }
+
+    pub fn set_last_status(&self, status: i32) {
+        self.lock().last_status = status;
+    }
+
+    /// The working directory commands are run in.
+    pub fn cwd(&self) -> PathBuf {
+        self.lock().cwd.clone()
+    }
+
+    pub fn set_cwd(&self, cwd: impl Into<PathBuf>) {
+        self.lock().cwd = cwd.into();
+    }
+
+    /// Resolves `path` against the working directory.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.lock().cwd.join(path)
+    }
+
+    pub fn options(&self) -> ShellOptions {
+        self.lock().options.clone()
+    }
+
+    /// Sets the option called `name`, returning `false` if there is no such
+    /// option.
+    pub fn set_option(&self, name: &str, value: bool) -> bool {
+        match self.lock().options.get_mut(name) {
+            Some(option) => {
+                *option = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends every job started in this shell, or any copy of it made from
+    /// now on, to the job table at the other end of `jobs`.
+    pub fn set_job_sender(&self, jobs: Sender<JobRequest>) {
+        self.lock().jobs = Some(jobs);
+    }
+
+    /// Hands `job` off to the job table to run alongside whatever else is
+    /// running.
+    pub fn spawn_job(&self, job: BackgroundJob) {
+        let mut inner = self.lock();
+        inner.last_background = job.process.child.id();
+
+        match &inner.jobs {
+            // the table only goes away when the shell is exiting
+            Some(jobs) => {
+                jobs.send(JobRequest::Spawn(Box::new(job))).ok();
+            }
+            // nothing is keeping track of jobs, so just let it run to
+            // completion
+            None => {
+                tokio::task::spawn(async move {
+                    let mut delegate = ExecutionDelegate::spawn(job.process).await;
+                    while delegate.rx.recv().await.is_some() {}
+                });
+            }
+        }
+    }
+
+    /// Runs `f` on the job table, returning `None` if there isn't one.
+    ///
+    /// The table belongs to the shell's main loop, which only gets to this
+    /// once whatever it is currently doing is done, so this can't be awaited
+    /// while a command line is still being started.
+    pub async fn with_jobs<T, F>(&self, f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut JobTable) -> T + Send + 'static,
+    {
+        let jobs = self.lock().jobs.clone()?;
+        let (reply, response) = oneshot::channel();
+
+        jobs.send(JobRequest::Run(Box::new(move |table| {
+            reply.send(f(table)).ok();
+        })))
+        .ok()?;
+
+        response.await.ok()
+    }
+
+    pub fn last_background(&self) -> Option<u32> {
+        self.lock().last_background
+    }
+
+    pub fn loop_depth(&self) -> usize {
+        self.lock().loop_depth
+    }
+
+    pub fn enter_loop(&self) -> LoopGuard {
+        self.lock().loop_depth += 1;
+
+        LoopGuard { ctx: self.clone() }
+    }
+
+    /// Whether a `break` or `continue` is stopping the commands after it
+    /// from running.
+    pub fn control_pending(&self) -> bool {
+        self.lock().control.is_some()
+    }
+
+    pub fn set_control(&self, control: Control) {
+        self.lock().control = Some(control);
+    }
+
+    pub fn take_control(&self) -> Option<Control> {
+        self.lock().control.take()
+    }
+
+    pub fn function(&self, name: &str) -> Option<Arc<ExecutionPlan>> {
+        self.lock().functions.get(name).cloned()
+    }
+
+    pub fn define_function(&self, name: &str, body: ExecutionPlan) {
+        self.lock()
+            .functions
+            .insert(name.to_owned(), Arc::new(body));
+    }
+
+    /// Removes the function called `name`, returning whether there was one.
+    pub fn unset_function(&self, name: &str) -> bool {
+        self.lock().functions.remove(name).is_some()
+    }
+
+    pub fn positional(&self) -> Vec<String> {
+        self.lock().positional.clone()
+    }
+
+    /// Starts a call to a function with `args` as its positional parameters.
+    pub fn enter_function(&self, args: Vec<String>) -> FunctionGuard {
+        let mut inner = self.lock();
+        inner.locals.push(HashMap::new());
+
+        FunctionGuard {
+            ctx: self.clone(),
+            positional: mem::replace(&mut inner.positional, args),
+        }
+    }
+
+    pub fn in_function(&self) -> bool {
+        !self.lock().locals.is_empty()
+    }
+
+    /// Makes `name` local to the current function, setting it to `value` or
+    /// unsetting it if there is none. Returns `false` if no function is
+    /// running.
+    pub fn set_local(&self, name: &str, value: Option<String>) -> bool {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+
+        let Some(locals) = inner.locals.last_mut() else {
+            return false;
+        };
+
+        locals
+            .entry(name.to_owned())
+            .or_insert_with(|| inner.variables.get(name).cloned());
+
+        match value {
+            Some(value) => {
+                inner.variables.insert(
+                    name.to_owned(),
+                    Variable {
+                        value,
+                        exported: false,
+                    },
+                );
+            }
+            None => {
+                inner.variables.remove(name);
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,596 @@
+//! Evaluates the integer expressions of `$(( ))` and `(( ))`.
+//!
+//! Operators follow C, from lowest to highest precedence:
+//!
+//! ```text
+//! ,
+//! = += -= *= /= %= <<= >>= &= ^= |=
+//! ?:
+//! ||
+//! &&
+//! |
+//! ^
+//! &
+//! == !=
+//! < <= > >=
+//! << >>
+//! + -
+//! * / %
+//! **
+//! ! ~ + - (unary)
+//! ++ -- (prefix and postfix)
+//! ```
+//!
+//! Numbers can be written in hex (`0x1f`), octal (`017`) or any base from 2
+//! to 64 (`2#1011`). Variables are referred to by name, and their values are
+//! themselves evaluated as expressions, with unset and empty ones counting as
+//! 0. All math is done on wrapping 64 bit integers.
+
+use thiserror::Error;
+
+use crate::context::ShellContext;
+
+#[derive(Debug, Error)]
+pub enum ArithmeticError {
+    #[error("syntax error: operand expected (error token is \"{0}\")")]
+    OperandExpected(String),
+    #[error("syntax error in expression (error token is \"{0}\")")]
+    Syntax(String),
+    #[error("value too great for base (error token is \"{0}\")")]
+    InvalidNumber(String),
+    #[error("attempted assignment to non-variable (error token is \"{0}\")")]
+    NotAVariable(String),
+    #[error("division by 0")]
+    DivisionByZero,
+    #[error("exponent less than 0")]
+    NegativeExponent,
+    #[error("{0}: expression recursion level exceeded")]
+    Recursion(String),
+}
+
+/// How deeply variables can refer to other variables before giving up, so
+/// that `x=x` doesn't recurse forever.
+const MAX_DEPTH: usize = 64;
+
+/// Evaluates `expression`, reading and assigning variables in `ctx`.
+pub fn evaluate(expression: &str, ctx: &ShellContext) -> Result<i64, ArithmeticError> {
+    evaluate_at(expression, ctx, 0)
+}
+
+fn evaluate_at(expression: &str, ctx: &ShellContext, depth: usize) -> Result<i64, ArithmeticError> {
+    let tokens = tokenize(expression)?;
+
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser {
+        source: expression,
+        tokens,
+        pos: 0,
+    };
+
+    let expr = parser.parse_comma()?;
+
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(ArithmeticError::Syntax(
+            expression[token.start..].trim().to_owned(),
+        ));
+    }
+
+    Evaluator { ctx, depth }.eval(&expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Where the token starts in the expression, for error messages.
+    start: usize,
+}
+
+/// Every operator, longest first so that the lexer finds `<<=` before `<<`
+/// and `<`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=",
+    "%=", "&=", "^=", "|=", "++", "--", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", ",", "=", "(", ")",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, ArithmeticError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < expression.len() {
+        let rest = &expression[i..];
+        let c = rest.chars().next().unwrap();
+
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+
+        let (kind, len) = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '_')))
+                .unwrap_or(rest.len());
+            (TokenKind::Number(parse_number(&rest[..len])?), len)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (TokenKind::Name(rest[..len].to_owned()), len)
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => (TokenKind::Op(op), op.len()),
+                None => return Err(ArithmeticError::Syntax(rest.trim().to_owned())),
+            }
+        };
+
+        tokens.push(Token { kind, start: i });
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+/// Parses a decimal, `0x` hex, `0` octal or `base#digits` number.
+fn parse_number(s: &str) -> Result<i64, ArithmeticError> {
+    let invalid = || ArithmeticError::InvalidNumber(s.to_owned());
+
+    let (base, digits) = if let Some((base, digits)) = s.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base @ 2..=64) => (base, digits),
+            _ => return Err(invalid()),
+        }
+    } else if let Some(digits) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        (16, digits)
+    } else if s.len() > 1 && s.starts_with('0') {
+        (8, &s[1..])
+    } else {
+        (10, s)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    digits.chars().try_fold(0i64, |value, c| {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            // letters are case insensitive unless the base needs both cases
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+
+        if digit >= base {
+            return Err(invalid());
+        }
+
+        Ok(value.wrapping_mul(base as i64).wrapping_add(digit as i64))
+    })
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    /// `name = value`, or `name op= value` when there is an operator.
+    Assign(String, Option<&'static str>, Box<Expr>),
+    /// `++name`, `name--` and friends.
+    Increment {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn parse_comma(&mut self) -> Result<Expr, ArithmeticError> {
+        let mut left = self.parse_assignment()?;
+
+        while self.eat(",") {
+            let right = self.parse_assignment()?;
+            left = Expr::Comma(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, ArithmeticError> {
+        let operator = match self.tokens.get(self.pos + 1).map(|token| &token.kind) {
+            Some(TokenKind::Op(op))
+                if op.ends_with('=') && !matches!(*op, "==" | "!=" | "<=" | ">=") =>
+            {
+                op
+            }
+            _ => return self.parse_conditional(),
+        };
+
+        let name = match &self.tokens[self.pos].kind {
+            TokenKind::Name(name) => name.clone(),
+            _ => return Err(ArithmeticError::NotAVariable(self.rest())),
+        };
+
+        self.pos += 2;
+
+        let operator = match operator.strip_suffix('=').unwrap() {
+            "" => None,
+            op => OPERATORS.iter().find(|known| **known == op).copied(),
+        };
+
+        Ok(Expr::Assign(
+            name,
+            operator,
+            Box::new(self.parse_assignment()?),
+        ))
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr, ArithmeticError> {
+        let condition = self.parse_binary(0)?;
+
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+
+        let then = self.parse_assignment()?;
+        self.expect(":")?;
+        let otherwise = self.parse_conditional()?;
+
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// Parses binary operators that bind at least as tightly as
+    /// `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ArithmeticError> {
+        let mut left = self.parse_unary()?;
+
+        while let Some(TokenKind::Op(op)) = self.tokens.get(self.pos).map(|token| &token.kind) {
+            let op = *op;
+
+            let Some(precedence) = precedence(op).filter(|p| *p >= min_precedence) else {
+                break;
+            };
+
+            self.pos += 1;
+
+            // `**` is the only right associative one
+            let next = if op == "**" {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.parse_binary(next)?;
+
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ArithmeticError> {
+        for op in ["++", "--"] {
+            if self.eat(op) {
+                return match self.next_kind() {
+                    Some(TokenKind::Name(name)) => Ok(Expr::Increment {
+                        name,
+                        delta: if op == "++" { 1 } else { -1 },
+                        prefix: true,
+                    }),
+                    _ => Err(ArithmeticError::OperandExpected(
+                        self.rest_from(self.pos - 1),
+                    )),
+                };
+            }
+        }
+
+        for op in ["+", "-", "!", "~"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.parse_unary()?)));
+            }
+        }
+
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ArithmeticError> {
+        let expr = self.parse_primary()?;
+
+        if let Expr::Variable(name) = &expr {
+            for (op, delta) in [("++", 1), ("--", -1)] {
+                if self.eat(op) {
+                    return Ok(Expr::Increment {
+                        name: name.clone(),
+                        delta,
+                        prefix: false,
+                    });
+                }
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ArithmeticError> {
+        let rest = self.rest();
+
+        match self.next_kind() {
+            Some(TokenKind::Number(n)) => Ok(Expr::Number(n)),
+            Some(TokenKind::Name(name)) => Ok(Expr::Variable(name)),
+            Some(TokenKind::Op("(")) => {
+                let expr = self.parse_comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err(ArithmeticError::OperandExpected(rest)),
+        }
+    }
+
+    fn next_kind(&mut self) -> Option<TokenKind> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token.kind.clone())
+    }
+
+    /// Consumes the next token if it is the operator `op`.
+    fn eat(&mut self, op: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token {
+                kind: TokenKind::Op(next),
+                ..
+            }) if *next == op => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ArithmeticError> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(ArithmeticError::Syntax(self.rest()))
+        }
+    }
+
+    /// The source from the next token on, for error messages.
+    fn rest(&self) -> String {
+        self.rest_from(self.pos)
+    }
+
+    fn rest_from(&self, pos: usize) -> String {
+        match self.tokens.get(pos) {
+            Some(token) => self.source[token.start..].trim().to_owned(),
+            None => String::new(),
+        }
+    }
+}
+
+/// How tightly a binary operator binds, or `None` if `op` isn't one.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 0,
+        "&&" => 1,
+        "|" => 2,
+        "^" => 3,
+        "&" => 4,
+        "==" | "!=" => 5,
+        "<" | "<=" | ">" | ">=" => 6,
+        "<<" | ">>" => 7,
+        "+" | "-" => 8,
+        "*" | "/" | "%" => 9,
+        "**" => 10,
+        _ => return None,
+    })
+}
+
+struct Evaluator<'a> {
+    ctx: &'a ShellContext,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &Expr) -> Result<i64, ArithmeticError> {
+        Ok(match expr {
+            Expr::Number(n) => *n,
+            Expr::Variable(name) => self.variable(name)?,
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    _ => value,
+                }
+            }
+            // the logical operators only evaluate their right side if they
+            // have to
+            Expr::Binary("&&", left, right) => {
+                (self.eval(left)? != 0 && self.eval(right)? != 0) as i64
+            }
+            Expr::Binary("||", left, right) => {
+                (self.eval(left)? != 0 || self.eval(right)? != 0) as i64
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                apply(op, left, right)?
+            }
+            Expr::Assign(name, op, value) => {
+                let value = self.eval(value)?;
+                let value = match op {
+                    Some(op) => apply(op, self.variable(name)?, value)?,
+                    None => value,
+                };
+                self.ctx.set_var(name, value.to_string());
+                value
+            }
+            Expr::Increment {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = self.variable(name)?;
+                let new = old.wrapping_add(*delta);
+                self.ctx.set_var(name, new.to_string());
+                if *prefix {
+                    new
+                } else {
+                    old
+                }
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+            Expr::Comma(left, right) => {
+                self.eval(left)?;
+                self.eval(right)?
+            }
+        })
+    }
+
+    fn variable(&self, name: &str) -> Result<i64, ArithmeticError> {
+        let value = self.ctx.var(name).unwrap_or_default();
+
+        if value.trim().is_empty() {
+            return Ok(0);
+        }
+
+        if self.depth >= MAX_DEPTH {
+            return Err(ArithmeticError::Recursion(name.to_owned()));
+        }
+
+        evaluate_at(&value, self.ctx, self.depth + 1)
+    }
+}
+
+fn apply(op: &str, left: i64, right: i64) -> Result<i64, ArithmeticError> {
+    Ok(match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err(ArithmeticError::DivisionByZero),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err(ArithmeticError::NegativeExponent),
+        "**" => left.wrapping_pow(u32::try_from(right).unwrap_or(u32::MAX)),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "&" => left & right,
+        "^" => left ^ right,
+        "|" => left | right,
+        _ => unreachable!("{} is not a binary operator", op),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> i64 {
+        evaluate(expression, &ShellContext::default()).unwrap()
+    }
+
+    #[test]
+    fn precedence_follows_c() {
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("2 ** 3 ** 2"), 512);
+        assert_eq!(eval("-2 ** 2"), 4);
+        assert_eq!(eval("1 << 2 + 1"), 8);
+        assert_eq!(eval("1 | 2 ^ 3 & 4"), 3);
+        assert_eq!(eval("1 < 2 == 1"), 1);
+        assert_eq!(eval("0 || 1 && 0"), 0);
+        assert_eq!(eval("1 ? 2 : 0 ? 3 : 4"), 2);
+        assert_eq!(eval("!0 + ~0"), 0);
+        assert_eq!(eval("7 / 2, 7 % -2"), 1);
+        assert_eq!(eval("-7 / 2"), -3);
+        assert_eq!(eval(""), 0);
+    }
+
+    #[test]
+    fn numbers_in_other_bases() {
+        assert_eq!(eval("0x1f + 017 + 2#1011"), 31 + 15 + 11);
+        assert_eq!(eval("36#z + 64#_"), 35 + 63);
+        assert!(matches!(
+            evaluate("08", &ShellContext::default()),
+            Err(ArithmeticError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn math_wraps_around() {
+        assert_eq!(eval("9223372036854775807 + 1"), i64::MIN);
+        assert_eq!(eval("-9223372036854775808 / -1"), i64::MIN);
+    }
+
+    #[test]
+    fn variables() {
+        let ctx = ShellContext::default();
+        ctx.set_var("a", "3");
+        ctx.set_var("b", "a * 2");
+        ctx.set_var("empty", "");
+
+        assert_eq!(evaluate("a + b + empty + unset", &ctx).unwrap(), 9);
+        assert_eq!(evaluate("c = a++ + ++a", &ctx).unwrap(), 8);
+        assert_eq!(ctx.var("a").as_deref(), Some("5"));
+        assert_eq!(ctx.var("c").as_deref(), Some("8"));
+        assert_eq!(evaluate("c -= 2, c <<= 1", &ctx).unwrap(), 12);
+        assert_eq!(evaluate("0 && (d = 1)", &ctx).unwrap(), 0);
+        assert_eq!(ctx.var("d"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let ctx = ShellContext::default();
+        ctx.set_var("x", "x");
+
+        let error = |expression| evaluate(expression, &ctx).unwrap_err();
+
+        assert!(matches!(error("1 / 0"), ArithmeticError::DivisionByZero));
+        assert!(matches!(error("1 % 0"), ArithmeticError::DivisionByZero));
+        assert!(matches!(
+            error("2 ** -1"),
+            ArithmeticError::NegativeExponent
+        ));
+        assert!(matches!(error("1 +"), ArithmeticError::OperandExpected(_)));
+        assert!(matches!(error("1 2"), ArithmeticError::Syntax(token) if token == "2"));
+        assert!(matches!(error("1 = 2"), ArithmeticError::NotAVariable(_)));
+        assert!(matches!(error("x + 1"), ArithmeticError::Recursion(_)));
+    }
+}
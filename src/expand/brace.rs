@@ -0,0 +1,278 @@
+use crate::parse::{
+    scan,
+    word::{Quoting, Word},
+};
+
+/// Performs brace expansion on `word`, leaving any braces inside of quotes
+/// alone.
+pub fn expand_word(word: &Word) -> Vec<Word> {
+    let braces = word
+        .segments
+        .iter()
+        .any(|segment| segment.quoting == Quoting::Unquoted && segment.value.contains('{'));
+
+    if !braces {
+        return vec![word.clone()];
+    }
+
+    expand(&word.source())
+        .into_iter()
+        .map(|source| {
+            // the quotes in each alternative are still balanced, so this
+            // can't fail
+            Word::parse(&source, word.span.clone())
+                .unwrap_or_else(|_| Word::new(source, Quoting::Unquoted, word.span.clone()))
+        })
+        .collect()
+}
+
+/// Performs brace expansion on the word written as `s`, so `a{b,c}d` becomes
+/// `abd acd` and `{1..3}` becomes `1 2 3`. Braces that don't hold a list or a
+/// sequence are left alone.
+pub fn expand(s: &str) -> Vec<String> {
+    let mut from = 0;
+
+    while let Some((open, close)) = find_braces(s, from) {
+        let body = &s[open + 1..close];
+
+        let alternatives = match split_list(body) {
+            Some(list) => list.into_iter().map(String::from).collect(),
+            None => match sequence(body) {
+                Some(sequence) => sequence,
+                None => {
+                    from = open + 1;
+                    continue;
+                }
+            },
+        };
+
+        let (prefix, suffix) = (&s[..open], &s[close + 1..]);
+
+        return alternatives
+            .iter()
+            .flat_map(|alternative| expand(&format!("{}{}{}", prefix, alternative, suffix)))
+            .collect();
+    }
+
+    vec![s.to_owned()]
+}
+
+/// Finds the first pair of braces at or after `from`, skipping over escaped
+/// characters, quotes, parameters and command substitutions.
+fn find_braces(s: &str, from: usize) -> Option<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let mut open = None;
+    let mut depth = 0;
+    let mut i = from;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', _) => i += 1,
+            (b'$', Some(b'\'')) => i += 2 + scan::ansi_c_quote_end(&s[i + 2..])?,
+            (b'\'', _) => i += 1 + s[i + 1..].find('\'')?,
+            (b'"', _) => i += 1 + scan::double_quote_end(&s[i + 1..])?,
+            (b'$', Some(b'{')) => i += 2 + scan::parameter_end(&s[i + 2..])?,
+            (b'$', Some(b'(')) => i += 2 + scan::substitution_end(&s[i + 2..])?,
+            (b'`', _) => i += 1 + scan::backtick_end(&s[i + 1..])?,
+            (b'{', _) => {
+                open.get_or_insert(i);
+                depth += 1;
+            }
+            (b'}', _) if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open?, i));
+                }
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Splits the body of `{a,b,c}` on its top level commas, or returns `None`
+/// if there are none.
+fn split_list(body: &str) -> Option<Vec<&str>> {
+    let bytes = body.as_bytes();
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'\'') => {
+                i += 2 + scan::ansi_c_quote_end(&body[i + 2..])?
+            }
+            b'\'' => i += 1 + body[i + 1..].find('\'')?,
+            b'"' => i += 1 + scan::double_quote_end(&body[i + 1..])?,
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                parts.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+
+    parts.push(&body[start..]);
+
+    Some(parts)
+}
+
+/// Expands the body of `{x..y}` or `{x..y..step}`, where `x` and `y` are
+/// either both integers or both single letters.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let mut parts = body.split("..");
+
+    let (start, end) = (parts.next()?, parts.next()?);
+    let step = match parts.next() {
+        // like the bounds, the size of the step has to fit
+        Some(step) => step.parse::<i64>().ok()?.checked_abs()?.max(1),
+        None => 1,
+    };
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // `{01..10}` pads every number to the same width
+        let padded = [start, end].iter().any(|n| {
+            n.trim_start_matches('-').len() > 1 && n.trim_start_matches('-').starts_with('0')
+        });
+        let width = if padded {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        return Some(
+            range(first, last, step)
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                range(first as i64, last as i64, step)
+                    .filter_map(|c| char::from_u32(c as u32))
+                    .map(String::from)
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Counts from `first` to `last` inclusive, in either direction.
+fn range(first: i64, last: i64, step: i64) -> impl Iterator<Item = i64> {
+    // the distance between the two can be too far for 64 bits, but every
+    // number in between fits
+    let (first, last, step) = (first as i128, last as i128, step as i128);
+    let count = (last - first).abs() / step;
+    let step = if last < first { -step } else { step };
+
+    (0..=count).map(move |i| (first + i * step) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("{a,{b,c}d}"), ["a", "bd", "cd"]);
+        assert_eq!(expand("{,x}y"), ["y", "xy"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(expand("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand("{0..10..5}"), ["0", "5", "10"]);
+        assert_eq!(expand("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand("{08..10}"), ["08", "09", "10"]);
+        assert_eq!(expand("{a..c}"), ["a", "b", "c"]);
+        assert_eq!(expand("{1..3..-2}"), ["1", "3"]);
+        assert_eq!(
+            expand("{-9223372036854775808..9223372036854775807..9223372036854775807}"),
+            ["-9223372036854775808", "-1", "9223372036854775806"]
+        );
+        assert_eq!(
+            expand("{9223372036854775807..9223372036854775806}"),
+            ["9223372036854775807", "9223372036854775806"]
+        );
+    }
+
+    #[test]
+    fn sequences_that_cant_be_counted_are_left_alone() {
+        for s in [
+            "{1..3..-9223372036854775808}",
+            "{1..9223372036854775808}",
+            "{1..2..9223372036854775808}",
+        ] {
+            assert_eq!(expand(s), [s]);
+        }
+    }
+
+    #[test]
+    fn other_braces_are_left_alone() {
+        for s in [
+            "{}",
+            "{a}",
+            "{a..}",
+            "{1..b}",
+            "a{b",
+            "a}b,c",
+            "{1..2..3..4}",
+        ] {
+            assert_eq!(expand(s), [s]);
+        }
+
+        assert_eq!(expand("{a}{b,c}"), ["{a}b", "{a}c"]);
+    }
+
+    #[test]
+    fn quoted_braces_are_left_alone() {
+        for s in [
+            r"\{a,b}",
+            "'{a,b}'",
+            "\"{a,b}\"",
+            "$'{a,b}'",
+            "${x:-{a,b\\}}",
+            "$(echo {a,b})",
+            "`echo {a,b}`",
+        ] {
+            assert_eq!(expand(s), [s]);
+        }
+
+        assert_eq!(expand("{'a,b',c}"), ["'a,b'", "c"]);
+        assert_eq!(expand("{\"}\",c}"), ["\"}\"", "c"]);
+    }
+}
@@ -0,0 +1,122 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{Expander, ExpansionError};
+use crate::{
+    parse::parse_command,
+    process::{status::BuiltinExitStatus, VashProcess},
+};
+
+impl Expander<'_> {
+    /// Runs `command` in a copy of the shell and returns what it wrote to
+    /// stdout, minus any trailing newlines.
+    pub(super) async fn substitute(&mut self, command: &str) -> Result<String, ExpansionError> {
+        let plan = parse_command(command)?;
+
+        // like a subshell, nothing the command does can leak back out
+        let ctx = self.ctx.fork();
+
+        let VashProcess {
+            mut stdin,
+            stdout: mut stdout_stream,
+            stderr: mut stderr_stream,
+            mut child,
+        } = plan.execute(&ctx).await;
+
+        stdin.shutdown().await.ok();
+        drop(stdin);
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        // both streams have to be drained at once, or a command that fills
+        // up one of them would never finish
+        let _ = tokio::join!(
+            stdout_stream.read_to_end(&mut stdout),
+            stderr_stream.read_to_end(&mut stderr),
+        );
+
+        self.stderr.append(&mut stderr);
+
+        let status = child
+            .wait()
+            .await
+            .unwrap_or_else(|_| BuiltinExitStatus::new_failure().into());
+        self.ctx.set_last_status(status.status_code());
+        self.status = Some(status);
+
+        let mut output = String::from_utf8_lossy(&stdout).into_owned();
+        output.truncate(output.trim_end_matches('\n').len());
+
+        Ok(output)
+    }
+}
+
+/// Removes the backslashes that only serve to escape `$`, `` ` `` and `\`
+/// inside of a backtick substitution.
+pub fn unescape_backticks(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('$' | '`' | '\\')) => res.extend(chars.next()),
+            _ => res.push(c),
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn output_is_substituted() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, r#"echo "[$(printf 'a\n\nb\n\n')]""#).await,
+            "[a\n\nb]\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "echo $(echo $(echo deep)) `echo back`").await,
+            "deep back\n"
+        );
+        assert_eq!(
+            stdout(
+                &ctx,
+                r#"for w in $(echo 'a  b') "$(echo 'c  d')"; do echo "[$w]"; done"#
+            )
+            .await,
+            "[a]\n[b]\n[c  d]\n"
+        );
+
+        let output = run(&ctx, "echo $(echo err >&2; echo out)").await;
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+    }
+
+    #[tokio::test]
+    async fn substitutions_run_in_a_copy_of_the_shell() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "x=1; y=$(x=2; echo $x); echo $x $y").await,
+            "1 2\n"
+        );
+        assert_eq!(stdout(&ctx, "x=$(exit 3); echo $?").await, "3\n");
+    }
+
+    #[test]
+    fn backticks_unescape_only_what_they_have_to() {
+        assert_eq!(
+            unescape_backticks(r"echo \$x \`a\` \\ \n"),
+            r"echo $x `a` \ \n"
+        );
+    }
+}
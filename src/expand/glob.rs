@@ -0,0 +1,228 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::pattern::Pattern;
+
+/// A path matched so far, both as it will be shown and as it is on disk.
+type Match = (String, PathBuf);
+
+/// Finds the paths matching `pattern`, which is relative to `cwd` unless it
+/// is absolute. Each `/` separated component is a [`Pattern`], except for
+/// `**`, which matches any number of directories.
+pub fn glob(pattern: &str, cwd: &Path) -> Vec<String> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => ((String::from("/"), PathBuf::from("/")), rest),
+        None => ((String::new(), cwd.to_path_buf()), pattern),
+    };
+
+    // a trailing `/` only matches directories
+    let (rest, dirs_only) = match rest.strip_suffix('/') {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+
+    let components = rest
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut matches = vec![root];
+
+    for (idx, component) in components.iter().enumerate() {
+        let last = idx == components.len() - 1;
+
+        matches = matches
+            .iter()
+            .flat_map(|(shown, path)| expand_component(component, shown, path, last))
+            .collect();
+    }
+
+    let mut matches = matches
+        .into_iter()
+        .filter(|(_, path)| !dirs_only || path.is_dir())
+        .map(|(shown, _)| if dirs_only { shown + "/" } else { shown })
+        .collect::<Vec<_>>();
+
+    matches.sort();
+    matches
+}
+
+fn expand_component(component: &str, shown: &str, path: &Path, last: bool) -> Vec<Match> {
+    if component == "**" {
+        let mut matches = Vec::new();
+
+        // in the middle of a path, `**` can also match no directories at all
+        if !last {
+            matches.push((shown.to_owned(), path.to_path_buf()));
+        }
+
+        descendants(shown, path, last, &mut matches);
+
+        return matches;
+    }
+
+    let pattern = Pattern::new(component);
+
+    if let Some(name) = pattern.as_literal() {
+        let path = path.join(&name);
+
+        return match path.symlink_metadata() {
+            Ok(_) => vec![(join(shown, &name), path)],
+            Err(_) => Vec::new(),
+        };
+    }
+
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+
+            if name.starts_with('.') && !pattern.matches_hidden() {
+                return None;
+            }
+
+            if !pattern.matches(&name) || (!last && !entry.path().is_dir()) {
+                return None;
+            }
+
+            Some((join(shown, &name), entry.path()))
+        })
+        .collect()
+}
+
+/// Collects everything below `path` that isn't hidden, or only the
+/// directories if `files` is not set. Symlinks are not followed.
+fn descendants(shown: &str, path: &Path, files: bool, matches: &mut Vec<Match>) {
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let is_dir = entry.file_type().map(|ty| ty.is_dir()).unwrap_or(false);
+        let shown = join(shown, &name);
+
+        if is_dir || files {
+            matches.push((shown.clone(), entry.path()));
+        }
+
+        if is_dir {
+            descendants(&shown, &entry.path(), files, matches);
+        }
+    }
+}
+
+fn join(shown: &str, name: &str) -> String {
+    if shown.is_empty() || shown.ends_with('/') {
+        format!("{}{}", shown, name)
+    } else {
+        format!("{}/{}", shown, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a fresh directory holding `paths`, where those ending in `/`
+    /// are directories.
+    fn tree(name: &str, paths: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("vash-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&root).ok();
+
+        for path in paths {
+            let path = root.join(path);
+            if path.to_string_lossy().ends_with('/') {
+                fs::create_dir_all(path).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+        }
+
+        root
+    }
+
+    #[test]
+    fn components_are_matched_in_turn() {
+        let root = tree(
+            "glob-components",
+            &["a.txt", "b.txt", "dir/c.txt", "dir/sub/d.txt", "other/"],
+        );
+
+        assert_eq!(glob("*.txt", &root), ["a.txt", "b.txt"]);
+        assert_eq!(glob("*", &root), ["a.txt", "b.txt", "dir", "other"]);
+        assert_eq!(glob("*/", &root), ["dir/", "other/"]);
+        assert_eq!(glob("*/*.txt", &root), ["dir/c.txt"]);
+        assert_eq!(glob("dir/*/d.txt", &root), ["dir/sub/d.txt"]);
+        assert_eq!(glob("[ab].txt", &root), ["a.txt", "b.txt"]);
+        assert!(glob("*.rs", &root).is_empty());
+
+        let absolute = format!("{}/?.txt", root.display());
+        assert_eq!(
+            glob(&absolute, Path::new("/")),
+            [
+                format!("{}/a.txt", root.display()),
+                format!("{}/b.txt", root.display())
+            ]
+        );
+
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn hidden_files_need_a_leading_dot() {
+        let root = tree("glob-hidden", &[".hidden", "shown", ".dir/x"]);
+
+        assert_eq!(glob("*", &root), ["shown"]);
+        assert_eq!(glob("?hidden", &root), Vec::<String>::new());
+        assert_eq!(glob(".*", &root), [".dir", ".hidden"]);
+        assert_eq!(glob(".d*/*", &root), [".dir/x"]);
+
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn double_stars_recurse() {
+        let root = tree(
+            "glob-recursive",
+            &["a.txt", "dir/b.txt", "dir/sub/c.txt", "dir/.hidden/d.txt"],
+        );
+
+        assert_eq!(
+            glob("**/*.txt", &root),
+            ["a.txt", "dir/b.txt", "dir/sub/c.txt"]
+        );
+        assert_eq!(glob("dir/**/", &root), ["dir/sub/"]);
+        assert_eq!(
+            glob("dir/**", &root),
+            ["dir/b.txt", "dir/sub", "dir/sub/c.txt"]
+        );
+
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn escaped_metacharacters_are_literal() {
+        let root = tree("glob-escaped", &["*", "a"]);
+
+        assert_eq!(glob(r"\*", &root), ["*"]);
+        assert_eq!(glob("*", &root), ["*", "a"]);
+        assert!(glob(r"\?", &root).is_empty());
+
+        fs::remove_dir_all(root).ok();
+    }
+}
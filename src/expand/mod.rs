@@ -0,0 +1,817 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use async_recursion::async_recursion;
+use thiserror::Error;
+
+use crate::{
+    context::ShellContext,
+    parse::{
+        scan,
+        unescape::{unescape, UnescapeError},
+        word::{self, Quoting, Word},
+        CommandParseError,
+    },
+    process::{
+        read::{ReadMerge, ReadSink, VashRead},
+        status::VashExitStatus,
+        VashProcess,
+    },
+};
+
+use self::{
+    arith::ArithmeticError,
+    param::{Modifier, Operator, Parameter},
+};
+
+pub mod arith;
+pub mod brace;
+pub mod command;
+pub mod glob;
+pub mod param;
+pub mod pattern;
+pub mod tilde;
+
+#[derive(Debug, Error)]
+pub enum ExpansionError {
+    #[error("{name}: {message}")]
+    Unset { name: String, message: String },
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    #[error(transparent)]
+    Unescape(#[from] UnescapeError),
+    #[error("command substitution: {0}")]
+    Substitution(#[from] CommandParseError),
+    #[error("no match: {0}")]
+    NoMatch(String),
+    #[error("{expression}: {error}")]
+    Arithmetic {
+        expression: String,
+        error: ArithmeticError,
+    },
+}
+
+/// How the text being expanded was quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Expansions are split into fields, and `\` escapes any character.
+    Unquoted,
+    /// Expansions are kept whole, and `\` only escapes `$`, `` ` ``, `"` and
+    /// `\`.
+    DoubleQuoted,
+    /// The body of a heredoc, where `\` only escapes `$`, `` ` ``, `\` and
+    /// newlines.
+    HereDoc,
+}
+
+/// Expands the words of a command using the state of a shell.
+pub struct Expander<'a> {
+    ctx: &'a ShellContext,
+    /// Everything the command substitutions wrote to stderr.
+    stderr: Vec<u8>,
+    /// The exit status of the last command substitution.
+    status: Option<VashExitStatus>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(ctx: &'a ShellContext) -> Self {
+        Self {
+            ctx,
+            stderr: Vec::new(),
+            status: None,
+        }
+    }
+
+    /// The exit status of the last command substitution, if there was one.
+    pub fn status(&self) -> Option<VashExitStatus> {
+        self.status
+    }
+
+    /// Hands whatever the command substitutions wrote to stderr over to
+    /// `process`, which is what the expanded words ended up running.
+    pub fn finish(self, mut process: VashProcess) -> VashProcess {
+        if !self.stderr.is_empty() {
+            let stderr = std::mem::replace(&mut process.stderr, VashRead::Sink(ReadSink));
+            process.stderr =
+                VashRead::Merged(ReadMerge::new([VashRead::Canned(self.stderr), stderr]));
+        }
+
+        process
+    }
+
+    /// Expands `words` into the fields that make up a command's arguments.
+    ///
+    /// Braces are expanded first, followed by tildes, parameters and command
+    /// substitutions, then field splitting and finally globs.
+    pub async fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, ExpansionError> {
+        let mut fields = Fields::new(true);
+
+        for word in words {
+            for word in brace::expand_word(word) {
+                self.expand_into(&word, &mut fields).await?;
+                fields.end_word();
+            }
+        }
+
+        let options = self.ctx.options();
+        let cwd = self.ctx.cwd();
+        let mut expanded = Vec::new();
+
+        for field in fields.finish() {
+            if !field.glob {
+                expanded.push(field.value);
+                continue;
+            }
+
+            let matches = glob::glob(&field.pattern, &cwd);
+
+            if !matches.is_empty() {
+                expanded.extend(matches);
+            } else if options.failglob {
+                return Err(ExpansionError::NoMatch(field.value));
+            } else if !options.nullglob {
+                expanded.push(field.value);
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    /// Expands `word` into a single string, without splitting it. This is
+    /// what assignments and redirection targets see.
+    pub async fn expand_word(&mut self, word: &Word) -> Result<String, ExpansionError> {
+        let mut fields = Fields::new(false);
+
+        self.expand_into(word, &mut fields).await?;
+
+        Ok(fields.into_string())
+    }
+
+    /// Expands `word` into a pattern to match against, where only the
+    /// unquoted glob characters are special.
+    pub async fn expand_pattern(&mut self, word: &Word) -> Result<String, ExpansionError> {
+        let mut fields = Fields::new(false);
+
+        self.expand_into(word, &mut fields).await?;
+
+        Ok(fields.into_pattern())
+    }
+
+    /// Expands the parameters and command substitutions in an arithmetic
+    /// expression, then evaluates it.
+    pub async fn expand_arithmetic(&mut self, expression: &str) -> Result<i64, ExpansionError> {
+        let expression = self.expand_operand(expression).await?;
+
+        arith::evaluate(&expression, self.ctx).map_err(|error| ExpansionError::Arithmetic {
+            expression: expression.trim().to_owned(),
+            error,
+        })
+    }
+
+    /// Expands the parameters in the body of a heredoc.
+    pub async fn expand_heredoc(&mut self, body: &str) -> Result<String, ExpansionError> {
+        let mut fields = Fields::new(false);
+
+        self.expand_str(body, Mode::HereDoc, &mut fields).await?;
+
+        Ok(fields.into_string())
+    }
+
+    /// Expands each segment of `word` into `fields`, according to how it was
+    /// quoted.
+    async fn expand_into(
+        &mut self,
+        word: &Word,
+        fields: &mut Fields,
+    ) -> Result<(), ExpansionError> {
+        for (i, segment) in word.segments.iter().enumerate() {
+            let value = segment.value.as_str();
+
+            match segment.quoting {
+                Quoting::Unquoted => {
+                    // a tilde prefix runs up to the first `/`, and isn't
+                    // expanded if any of it is quoted
+                    let tilde = match tilde::expand(value, self.ctx) {
+                        Some((_, rest))
+                            if i > 0 || (rest.is_empty() && word.segments.len() > 1) =>
+                        {
+                            None
+                        }
+                        tilde => tilde,
+                    };
+
+                    match tilde {
+                        Some((home, rest)) => {
+                            fields.push_str(&home);
+                            self.expand_str(rest, Mode::Unquoted, fields).await?;
+                        }
+                        None => self.expand_str(value, Mode::Unquoted, fields).await?,
+                    }
+                }
+                Quoting::Double => {
+                    // `""` is still an argument, even though it is empty
+                    fields.push_str("");
+                    self.expand_str(value, Mode::DoubleQuoted, fields).await?;
+                }
+                Quoting::Single => fields.push_str(value),
+                Quoting::AnsiC => fields.push_str(&unescape(value)?),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[async_recursion]
+    async fn expand_str(
+        &mut self,
+        s: &str,
+        mode: Mode,
+        fields: &mut Fields,
+    ) -> Result<(), ExpansionError> {
+        let mut chars = s.char_indices().peekable();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '$' if s[idx + 1..].starts_with("((")
+                    && scan::arithmetic_end(&s[idx + 3..]).is_some() =>
+                {
+                    let start = idx + 3;
+                    let end = start + scan::arithmetic_end(&s[start..]).unwrap();
+
+                    skip_to(&mut chars, end + 2);
+                    let value = self.expand_arithmetic(&s[start..end]).await?;
+                    self.push_value(&value.to_string(), mode, fields);
+                }
+                '$' if s[idx + 1..].starts_with('(') => {
+                    let start = idx + 2;
+                    let end = start
+                        + scan::substitution_end(&s[start..])
+                            .ok_or_else(|| ExpansionError::BadSubstitution(s[idx..].to_owned()))?;
+
+                    skip_to(&mut chars, end + 1);
+                    let output = self.substitute(&s[start..end]).await?;
+                    self.push_value(&output, mode, fields);
+                }
+                '$' => match param::parse(&s[idx + 1..])? {
+                    Some((param, len)) => {
+                        skip_to(&mut chars, idx + 1 + len);
+                        self.expand_parameter(&param, mode, fields).await?;
+                    }
+                    None => fields.push_str("$"),
+                },
+                '`' => {
+                    let start = idx + 1;
+                    let end = start
+                        + scan::backtick_end(&s[start..])
+                            .ok_or_else(|| ExpansionError::BadSubstitution(s[idx..].to_owned()))?;
+
+                    skip_to(&mut chars, end + 1);
+                    let output = self
+                        .substitute(&command::unescape_backticks(&s[start..end]))
+                        .await?;
+                    self.push_value(&output, mode, fields);
+                }
+                // the only quotes left by the lexer are in the word of a
+                // `${name:-word}`, which has quoting of its own
+                '"' if mode != Mode::HereDoc && scan::double_quote_end(&s[idx + 1..]).is_some() => {
+                    let start = idx + 1;
+                    let end = start + scan::double_quote_end(&s[start..]).unwrap();
+
+                    skip_to(&mut chars, end + 1);
+                    fields.push_str("");
+                    self.expand_str(&s[start..end], Mode::DoubleQuoted, fields)
+                        .await?;
+                }
+                '\'' if mode == Mode::Unquoted && s[idx + 1..].contains('\'') => {
+                    let start = idx + 1;
+                    let end = start + s[start..].find('\'').unwrap();
+
+                    skip_to(&mut chars, end + 1);
+                    fields.push_str(&s[start..end]);
+                }
+                '\\' => match (mode, chars.next()) {
+                    (_, None) => fields.push_str("\\"),
+                    // a line continuation
+                    (_, Some((_, '\n'))) => {}
+                    (Mode::Unquoted, Some((_, c))) => fields.push_char(c),
+                    (Mode::DoubleQuoted, Some((_, c))) if word::escapes_in_double_quotes(c) => {
+                        fields.push_char(c)
+                    }
+                    (Mode::HereDoc, Some((_, c @ ('$' | '`' | '\\')))) => fields.push_char(c),
+                    (Mode::DoubleQuoted | Mode::HereDoc, Some((_, c))) => {
+                        fields.push_char('\\');
+                        fields.push_char(c);
+                    }
+                },
+                c if mode == Mode::Unquoted => fields.push_unquoted(c),
+                c => fields.push_char(c),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[async_recursion]
+    async fn expand_parameter(
+        &mut self,
+        param: &Parameter<'_>,
+        mode: Mode,
+        fields: &mut Fields,
+    ) -> Result<(), ExpansionError> {
+        let value = self.lookup(param.name);
+
+        let (operator, null, word) = match param.modifier {
+            Modifier::None if matches!(param.name, "@" | "*") => {
+                self.push_positional(param.name, mode, fields);
+                return Ok(());
+            }
+            Modifier::None => {
+                self.push_value(&value.unwrap_or_default(), mode, fields);
+                return Ok(());
+            }
+            Modifier::Length => {
+                let len = value.unwrap_or_default().chars().count();
+                fields.push_str(&len.to_string());
+                return Ok(());
+            }
+            Modifier::Operator {
+                operator,
+                null,
+                word,
+            } => (operator, null, word),
+        };
+
+        let set = match &value {
+            Some(value) => !(null && value.is_empty()),
+            None => false,
+        };
+
+        match (operator, set) {
+            (Operator::Default, false) | (Operator::Alternative, true) => {
+                self.expand_str(word, mode, fields).await
+            }
+            (Operator::Alternative, false) => Ok(()),
+            (_, true) => {
+                self.push_value(&value.unwrap_or_default(), mode, fields);
+                Ok(())
+            }
+            (Operator::Assign, false) => {
+                let value = self.expand_operand(word).await?;
+                self.ctx.set_var(param.name, value.clone());
+                self.push_value(&value, mode, fields);
+                Ok(())
+            }
+            (Operator::Error, false) => {
+                let message = match self.expand_operand(word).await? {
+                    message if message.is_empty() => String::from("parameter null or not set"),
+                    message => message,
+                };
+                Err(ExpansionError::Unset {
+                    name: param.name.to_owned(),
+                    message,
+                })
+            }
+        }
+    }
+
+    /// Expands the word of a `${name:=word}` or `${name:?word}` into a
+    /// single string.
+    async fn expand_operand(&mut self, word: &str) -> Result<String, ExpansionError> {
+        let mut fields = Fields::new(false);
+
+        self.expand_str(word, Mode::Unquoted, &mut fields).await?;
+
+        Ok(fields.into_string())
+    }
+
+    fn push_value(&self, value: &str, mode: Mode, fields: &mut Fields) {
+        match mode {
+            Mode::Unquoted => fields.push_split(value, &self.ifs()),
+            Mode::DoubleQuoted | Mode::HereDoc => fields.push_str(value),
+        }
+    }
+
+    /// Pushes the positional parameters for `$@` or `$*`. Each one becomes
+    /// a separate field, except in a quoted `"$*"` where they are joined by
+    /// the first character of `$IFS`.
+    fn push_positional(&self, name: &str, mode: Mode, fields: &mut Fields) {
+        let params = self.ctx.positional();
+        let ifs = self.ifs();
+
+        match mode {
+            Mode::Unquoted => {
+                fields.push_fields(&params, |fields, param| fields.push_split(param, &ifs))
+            }
+            Mode::DoubleQuoted if name == "@" => {
+                if params.is_empty() {
+                    fields.vanish();
+                }
+
+                fields.push_fields(&params, Fields::push_str);
+            }
+            Mode::DoubleQuoted | Mode::HereDoc => {
+                let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+                fields.push_str(&params.join(&separator));
+            }
+        }
+    }
+
+    fn ifs(&self) -> String {
+        self.ctx.var("IFS").unwrap_or_else(|| String::from(" \t\n"))
+    }
+
+    /// Looks up the value of a parameter, including the special ones.
+    fn lookup(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.ctx.last_status().to_string()),
+            "$" => Some(std::process::id().to_string()),
+            "0" => Some(String::from("vash")),
+            "#" => Some(self.ctx.positional().len().to_string()),
+            "@" | "*" => Some(self.ctx.positional().join(" ")),
+            "-" => Some(String::new()),
+            "!" => self.ctx.last_background().map(|pid| pid.to_string()),
+            name if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?;
+                self.ctx.positional().get(index.checked_sub(1)?).cloned()
+            }
+            name => self.ctx.var(name),
+        }
+    }
+}
+
+/// Advances `chars` until it reaches the byte offset `end`.
+fn skip_to(chars: &mut Peekable<CharIndices>, end: usize) {
+    while matches!(chars.peek(), Some(&(idx, _)) if idx < end) {
+        chars.next();
+    }
+}
+
+/// The fields a series of words expands to.
+///
+/// A field is only started once something is pushed to it, so an unquoted
+/// word that expands to nothing disappears entirely.
+struct Fields {
+    fields: Vec<Field>,
+    current: Option<Field>,
+    split: bool,
+}
+
+#[derive(Default)]
+struct Field {
+    value: String,
+    /// The value as a glob pattern, with any quoted characters escaped.
+    pattern: String,
+    /// Whether an unquoted glob character made it into the field.
+    glob: bool,
+    /// Whether the field is dropped unless more is pushed to it.
+    vanish: bool,
+}
+
+impl Fields {
+    fn new(split: bool) -> Self {
+        Self {
+            fields: Vec::new(),
+            current: None,
+            split,
+        }
+    }
+
+    /// Pushes quoted text, which is never treated as a glob.
+    fn push_str(&mut self, s: &str) {
+        let field = self.current.get_or_insert_with(Field::default);
+
+        for c in s.chars() {
+            field.push(c, true);
+        }
+    }
+
+    /// Pushes a quoted character, which is never treated as a glob.
+    fn push_char(&mut self, c: char) {
+        self.current
+            .get_or_insert_with(Field::default)
+            .push(c, true);
+    }
+
+    /// Pushes an unquoted character, which may be part of a glob.
+    fn push_unquoted(&mut self, c: char) {
+        self.current
+            .get_or_insert_with(Field::default)
+            .push(c, false);
+    }
+
+    /// Pushes the result of an unquoted expansion, splitting it into
+    /// separate fields on the characters in `ifs`.
+    ///
+    /// Runs of whitespace count as one separator, but every other character
+    /// separates fields on its own, so `a::b` splits on `:` into `a`, an
+    /// empty field and `b`.
+    fn push_split(&mut self, s: &str, ifs: &str) {
+        // whether whitespace just ended a field, in which case the next
+        // separator is part of the same one
+        let mut separated = false;
+
+        for c in s.chars() {
+            if !self.split || !ifs.contains(c) {
+                self.push_unquoted(c);
+                separated = false;
+            } else if c.is_whitespace() {
+                separated |= self.current.is_some();
+                self.end_word();
+            } else {
+                if !separated {
+                    self.current.get_or_insert_with(Field::default);
+                }
+
+                self.end_word();
+                separated = false;
+            }
+        }
+    }
+
+    /// Pushes each of `values` as a separate field, or joined by spaces when
+    /// nothing is being split.
+    fn push_fields(&mut self, values: &[String], push: impl Fn(&mut Self, &str)) {
+        for (i, value) in values.iter().enumerate() {
+            match i {
+                0 => {}
+                _ if self.split => self.end_word(),
+                _ => push(self, " "),
+            }
+
+            push(self, value);
+        }
+    }
+
+    /// Drops the current field unless something else is pushed to it, which
+    /// is how `"$@"` with no positional parameters disappears entirely.
+    fn vanish(&mut self) {
+        if let Some(field) = &mut self.current {
+            field.vanish = field.value.is_empty();
+        }
+    }
+
+    fn end_word(&mut self) {
+        self.fields
+            .extend(self.current.take().filter(|field| !field.vanish));
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        self.end_word();
+        self.fields
+    }
+
+    /// Joins every field back together, for when nothing was split.
+    fn into_string(self) -> String {
+        self.finish().into_iter().map(|field| field.value).collect()
+    }
+
+    /// Joins every field back together as a pattern.
+    fn into_pattern(self) -> String {
+        self.finish()
+            .into_iter()
+            .map(|field| field.pattern)
+            .collect()
+    }
+}
+
+impl Field {
+    fn push(&mut self, c: char, quoted: bool) {
+        let special = matches!(c, '*' | '?' | '[');
+
+        if c == '\\' || (quoted && (special || c == ']')) {
+            self.pattern.push('\\');
+        }
+
+        self.value.push(c);
+        self.pattern.push(c);
+        self.vanish = false;
+        self.glob |= special && !quoted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cmd::execution_plan::ExecutionPlan, parse::parse_command};
+
+    use super::*;
+
+    /// Expands the arguments of the simple command `source`.
+    async fn expand(ctx: &ShellContext, source: &str) -> Result<Vec<String>, ExpansionError> {
+        let ExecutionPlan::Execute(cmd) = parse_command(source).unwrap() else {
+            panic!("{:?} isn't a simple command", source);
+        };
+
+        Expander::new(ctx).expand_words(&cmd.words).await
+    }
+
+    fn context() -> ShellContext {
+        let ctx = ShellContext::default();
+        ctx.set_var("x", "a  b");
+        ctx.set_var("empty", "");
+        ctx
+    }
+
+    #[tokio::test]
+    async fn segments_are_joined_into_one_field() {
+        let ctx = context();
+
+        assert_eq!(
+            expand(&ctx, r#"a"$x"'$x'$'\t'\ b"#).await.unwrap(),
+            ["aa  b$x\t b"]
+        );
+        assert_eq!(
+            expand(&ctx, r#"\$x \"a\" "\$x\\" '\'"#).await.unwrap(),
+            ["$x", "\"a\"", "$x\\", "\\"]
+        );
+        assert_eq!(expand(&ctx, "''$empty \"\"").await.unwrap(), ["", ""]);
+    }
+
+    #[tokio::test]
+    async fn escapes_are_decoded_in_ansi_c_quotes_only() {
+        let ctx = context();
+
+        assert_eq!(
+            expand(&ctx, r#"$'\xc3\xa9\t' "\303\251\t" "a\tb""#)
+                .await
+                .unwrap(),
+            ["é\t", r"\303\251\t", r"a\tb"]
+        );
+        assert_eq!(
+            expand(&ctx, r#""\." "s/\(x\)/\1/""#).await.unwrap(),
+            [r"\.", r"s/\(x\)/\1/"]
+        );
+        assert_eq!(
+            expand(&ctx, r#""\$x \"\\" $'\'$x'"#).await.unwrap(),
+            ["$x \"\\", "'$x"]
+        );
+    }
+
+    #[tokio::test]
+    async fn line_continuations_are_removed() {
+        let ctx = context();
+
+        assert_eq!(
+            expand(&ctx, "a\\\nb \\\n \"c\\\nd\" 'e\\\nf'")
+                .await
+                .unwrap(),
+            ["ab", "cd", "e\\\nf"]
+        );
+    }
+
+    #[tokio::test]
+    async fn parameters_are_split_unless_quoted() {
+        let ctx = context();
+
+        assert_eq!(expand(&ctx, "$x").await.unwrap(), ["a", "b"]);
+        assert_eq!(expand(&ctx, "\"$x\"").await.unwrap(), ["a  b"]);
+        assert_eq!(expand(&ctx, "${x}c").await.unwrap(), ["a", "bc"]);
+        assert_eq!(
+            expand(&ctx, "$empty $unset").await.unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(expand(&ctx, "\"$empty\" ''").await.unwrap(), ["", ""]);
+        assert_eq!(expand(&ctx, "${#x}").await.unwrap(), ["4"]);
+        assert_eq!(expand(&ctx, "$ \"$\"").await.unwrap(), ["$", "$"]);
+    }
+
+    #[tokio::test]
+    async fn ifs_decides_where_fields_split() {
+        let ctx = context();
+        ctx.set_var("IFS", ":");
+        ctx.set_var("path", "a::b");
+
+        assert_eq!(expand(&ctx, "$path").await.unwrap(), ["a", "", "b"]);
+        assert_eq!(expand(&ctx, "$x").await.unwrap(), ["a  b"]);
+
+        ctx.set_var("IFS", " :");
+        ctx.set_var("path", " :a : b:");
+
+        assert_eq!(expand(&ctx, "$path").await.unwrap(), ["", "a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn positional_parameters() {
+        let ctx = context();
+        let _guard = ctx.enter_function(vec![String::from("a b"), String::from("c")]);
+
+        assert_eq!(expand(&ctx, "$# $1").await.unwrap(), ["2", "a", "b"]);
+        assert_eq!(expand(&ctx, "\"$@\"").await.unwrap(), ["a b", "c"]);
+        assert_eq!(expand(&ctx, "\"$*\"").await.unwrap(), ["a b c"]);
+        assert_eq!(expand(&ctx, "$@").await.unwrap(), ["a", "b", "c"]);
+        assert_eq!(expand(&ctx, "\"${3}\"").await.unwrap(), [""]);
+    }
+
+    #[tokio::test]
+    async fn parameter_operators() {
+        let ctx = context();
+
+        assert_eq!(
+            expand(&ctx, "${unset-d} ${empty-d} ${empty:-d}")
+                .await
+                .unwrap(),
+            ["d", "d"]
+        );
+        assert_eq!(
+            expand(&ctx, "${x:+set} ${empty+set} ${empty:+set}")
+                .await
+                .unwrap(),
+            ["set", "set"]
+        );
+        assert_eq!(expand(&ctx, "${new:=$x}").await.unwrap(), ["a", "b"]);
+        assert_eq!(ctx.var("new").as_deref(), Some("a  b"));
+
+        assert!(matches!(
+            expand(&ctx, "${unset:?gone}").await,
+            Err(ExpansionError::Unset { name, message }) if name == "unset" && message == "gone"
+        ));
+    }
+
+    #[tokio::test]
+    async fn braces_then_tildes() {
+        let ctx = context();
+        ctx.set_var("HOME", "/home/me");
+
+        assert_eq!(
+            expand(&ctx, "~/{a,b} x{1..2}y").await.unwrap(),
+            ["/home/me/a", "/home/me/b", "x1y", "x2y"]
+        );
+        assert_eq!(
+            expand(&ctx, r#"a{"b c",'$x',$x}"#).await.unwrap(),
+            ["ab c", "a$x", "aa", "b"]
+        );
+        assert_eq!(
+            expand(&ctx, r#"'~' "~" \~ ~"/" a=~"#).await.unwrap(),
+            ["~", "~", "~", "~/", "a=~"]
+        );
+    }
+
+    #[tokio::test]
+    async fn arithmetic() {
+        let ctx = context();
+        ctx.set_var("n", "4");
+
+        assert_eq!(
+            expand(&ctx, "$((n * 2)) \"$(( $n + ${n} ))\" a$((1))b")
+                .await
+                .unwrap(),
+            ["8", "8", "a1b"]
+        );
+        assert_eq!(expand(&ctx, "$((n += 1)) $n").await.unwrap(), ["5", "5"]);
+        assert!(matches!(
+            expand(&ctx, "$((1 / 0))").await,
+            Err(ExpansionError::Arithmetic { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn operator_words_are_quoted_on_their_own() {
+        let ctx = context();
+
+        assert_eq!(
+            expand(&ctx, r#""${unset:-"quoted default"}""#)
+                .await
+                .unwrap(),
+            ["quoted default"]
+        );
+        assert_eq!(expand(&ctx, r#"${unset:-"a  b"}"#).await.unwrap(), ["a  b"]);
+        assert_eq!(expand(&ctx, "${unset:-'a  b'}").await.unwrap(), ["a  b"]);
+        assert_eq!(expand(&ctx, r#""${unset:-"}"}""#).await.unwrap(), ["}"]);
+        assert_eq!(expand(&ctx, r#""${unset:-it's}""#).await.unwrap(), ["it's"]);
+        assert_eq!(
+            expand(&ctx, r#""${unset:-${also:-"$x"}}""#).await.unwrap(),
+            ["a  b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn globs_unless_quoted() {
+        let ctx = context();
+        let dir = std::env::temp_dir().join(format!("vash-expand-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        std::fs::write(dir.join("b.txt"), "").unwrap();
+        ctx.set_cwd(&dir);
+        ctx.set_var("pattern", "*.txt");
+
+        assert_eq!(
+            expand(&ctx, "*.txt $pattern").await.unwrap(),
+            ["a.txt", "b.txt", "a.txt", "b.txt"]
+        );
+        assert_eq!(
+            expand(&ctx, r#""*.txt" '*'.txt \*.txt "$pattern""#)
+                .await
+                .unwrap(),
+            ["*.txt", "*.txt", "*.txt", "*.txt"]
+        );
+
+        assert_eq!(expand(&ctx, "*.rs").await.unwrap(), ["*.rs"]);
+        ctx.set_option("nullglob", true);
+        assert_eq!(expand(&ctx, "*.rs a").await.unwrap(), ["a"]);
+        ctx.set_option("failglob", true);
+        assert!(matches!(
+            expand(&ctx, "*.rs").await,
+            Err(ExpansionError::NoMatch(pattern)) if pattern == "*.rs"
+        ));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,234 @@
+use crate::parse::scan;
+
+use super::ExpansionError;
+
+/// A `$name` or `${...}` parameter expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter<'a> {
+    pub name: &'a str,
+    pub modifier: Modifier<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modifier<'a> {
+    None,
+    /// `${#name}`
+    Length,
+    /// `${name:-word}` and friends. `null` is set for the forms with a
+    /// colon, which treat an empty value the same as an unset one.
+    Operator {
+        operator: Operator,
+        null: bool,
+        word: &'a str,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `-`: use `word` if the parameter is unset.
+    Default,
+    /// `=`: assign `word` to the parameter if it is unset.
+    Assign,
+    /// `?`: fail with `word` as the message if the parameter is unset.
+    Error,
+    /// `+`: use `word` if the parameter is set.
+    Alternative,
+}
+
+/// Parses the parameter expansion at the start of `s`, which is everything
+/// following a `$`. Returns the expansion and how many bytes of `s` it took
+/// up, or `None` if the `$` is just a literal.
+pub fn parse(s: &str) -> Result<Option<(Parameter<'_>, usize)>, ExpansionError> {
+    let Some(first) = s.chars().next() else {
+        return Ok(None);
+    };
+
+    if first != '{' {
+        let len = match name_len(s) {
+            0 => return Ok(None),
+            len => len,
+        };
+
+        return Ok(Some((
+            Parameter {
+                name: &s[..len],
+                modifier: Modifier::None,
+            },
+            len,
+        )));
+    }
+
+    let end = closing_brace(s).ok_or_else(|| ExpansionError::BadSubstitution(s.to_owned()))?;
+    let inner = &s[1..end];
+    let bad = || ExpansionError::BadSubstitution(format!("${{{}}}", inner));
+
+    if let Some(name) = inner.strip_prefix('#').filter(|name| !name.is_empty()) {
+        if name_len(name) != name.len() {
+            return Err(bad());
+        }
+
+        return Ok(Some((
+            Parameter {
+                name,
+                modifier: Modifier::Length,
+            },
+            end + 1,
+        )));
+    }
+
+    let len = braced_name_len(inner);
+    if len == 0 {
+        return Err(bad());
+    }
+
+    let (name, rest) = inner.split_at(len);
+
+    let modifier = if rest.is_empty() {
+        Modifier::None
+    } else {
+        let (null, rest) = match rest.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        let operator = match rest.chars().next() {
+            Some('-') => Operator::Default,
+            Some('=') => Operator::Assign,
+            Some('?') => Operator::Error,
+            Some('+') => Operator::Alternative,
+            _ => return Err(bad()),
+        };
+
+        Modifier::Operator {
+            operator,
+            null,
+            word: &rest[1..],
+        }
+    };
+
+    Ok(Some((Parameter { name, modifier }, end + 1)))
+}
+
+/// The length of the unbraced parameter name at the start of `s`.
+fn name_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => s
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(s.len()),
+        // only a single digit is allowed without braces, `$10` is `${1}0`
+        Some(c) if c.is_ascii_digit() || is_special(c) => 1,
+        _ => 0,
+    }
+}
+
+/// Like [`name_len`], but positional parameters may have multiple digits.
+fn braced_name_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(c) if c.is_ascii_digit() => s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()),
+        _ => name_len(s),
+    }
+}
+
+fn is_special(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '@' | '*' | '!' | '-')
+}
+
+/// Finds the `}` closing the `{` at the start of `s`.
+fn closing_brace(s: &str) -> Option<usize> {
+    Some(1 + scan::parameter_end(s.strip_prefix('{')?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(s: &str) -> (Parameter<'_>, usize) {
+        parse(s).unwrap().unwrap()
+    }
+
+    #[test]
+    fn unbraced_names() {
+        let none = |name| Parameter {
+            name,
+            modifier: Modifier::None,
+        };
+
+        assert_eq!(parsed("foo_1-bar"), (none("foo_1"), 5));
+        assert_eq!(parsed("10"), (none("1"), 1));
+        assert_eq!(parsed("?x"), (none("?"), 1));
+        assert_eq!(parse("").unwrap(), None);
+        assert_eq!(parse(" x").unwrap(), None);
+    }
+
+    #[test]
+    fn braced_names_and_modifiers() {
+        assert_eq!(
+            parsed("{10}x"),
+            (
+                Parameter {
+                    name: "10",
+                    modifier: Modifier::None,
+                },
+                4
+            )
+        );
+        assert_eq!(
+            parsed("{#foo}"),
+            (
+                Parameter {
+                    name: "foo",
+                    modifier: Modifier::Length,
+                },
+                6
+            )
+        );
+        assert_eq!(
+            parsed("{foo:-bar baz}"),
+            (
+                Parameter {
+                    name: "foo",
+                    modifier: Modifier::Operator {
+                        operator: Operator::Default,
+                        null: true,
+                        word: "bar baz",
+                    },
+                },
+                14
+            )
+        );
+        assert_eq!(
+            parsed("{foo+}").0.modifier,
+            Modifier::Operator {
+                operator: Operator::Alternative,
+                null: false,
+                word: "",
+            }
+        );
+    }
+
+    #[test]
+    fn words_can_hold_braces() {
+        let (param, len) = parsed(r#"{x:-"}" ${y:-{\}} $(echo })}rest"#);
+
+        assert_eq!(len, 28);
+        assert_eq!(
+            param.modifier,
+            Modifier::Operator {
+                operator: Operator::Default,
+                null: true,
+                word: r#""}" ${y:-{\}} $(echo })"#,
+            }
+        );
+    }
+
+    #[test]
+    fn bad_substitutions() {
+        for s in ["{}", "{foo", "{foo!}", "{#a b}", "{a b}"] {
+            assert!(
+                matches!(parse(s), Err(ExpansionError::BadSubstitution(_))),
+                "{:?}",
+                s
+            );
+        }
+    }
+}
@@ -0,0 +1,272 @@
+/// A shell pattern, as used for globbing.
+///
+/// `*` matches any number of characters, `?` matches any single character and
+/// `[...]` matches any of the characters in the brackets. A `\` makes the
+/// character after it match literally.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<PatternToken>,
+}
+
+#[derive(Debug, Clone)]
+enum PatternToken {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`, or `[!...]` when `negated`.
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    /// `a-z`
+    Range(char, char),
+    /// `[:alpha:]` and friends.
+    Named(fn(&char) -> bool),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let token = match chars[i] {
+                '*' => PatternToken::Star,
+                '?' => PatternToken::Any,
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        i += len;
+                        token
+                    }
+                    None => PatternToken::Char('['),
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    PatternToken::Char(chars[i])
+                }
+                c => PatternToken::Char(c),
+            };
+
+            tokens.push(token);
+            i += 1;
+        }
+
+        Self { tokens }
+    }
+
+    /// The only string the pattern matches, if it has no wildcards.
+    pub fn as_literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                PatternToken::Char(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the pattern starts with a literal `.`, which is what it takes
+    /// for a glob to match hidden files.
+    pub fn matches_hidden(&self) -> bool {
+        matches!(self.tokens.first(), Some(PatternToken::Char('.')))
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let (mut p, mut t) = (0, 0);
+        // where to resume after the last `*` if the rest fails to match
+        let mut backtrack = None;
+
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(PatternToken::Star) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                Some(token) if token.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            }
+        }
+
+        self.tokens[p..]
+            .iter()
+            .all(|token| matches!(token, PatternToken::Star))
+    }
+}
+
+impl PatternToken {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(expected) => *expected == c,
+            Self::Any => true,
+            Self::Star => false,
+            Self::Class { negated, items } => items.iter().any(|item| item.matches(c)) != *negated,
+        }
+    }
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(expected) => *expected == c,
+            Self::Range(start, end) => (*start..=*end).contains(&c),
+            Self::Named(class) => class(&c),
+        }
+    }
+}
+
+/// Parses the class at the start of `chars`, which is everything following a
+/// `[`. Returns the class and how many characters it took up, including the
+/// closing `]`, or `None` if it is never closed.
+fn parse_class(chars: &[char]) -> Option<(PatternToken, usize)> {
+    let mut i = 0;
+
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+
+    // a `]` right at the start is part of the class rather than closing it
+    let start = i;
+
+    loop {
+        let c = match *chars.get(i)? {
+            ']' if i > start => break,
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let len = chars[i + 2..]
+                    .windows(2)
+                    .position(|window| window == [':', ']'])?;
+                let name = chars[i + 2..i + 2 + len].iter().collect::<String>();
+
+                items.push(ClassItem::Named(named_class(&name)?));
+                i += len + 4;
+                continue;
+            }
+            '\\' => {
+                i += 1;
+                *chars.get(i)?
+            }
+            c => c,
+        };
+
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                items.push(ClassItem::Range(c, end));
+                i += 3;
+            }
+            _ => {
+                items.push(ClassItem::Char(c));
+                i += 1;
+            }
+        }
+    }
+
+    Some((PatternToken::Class { negated, items }, i + 1))
+}
+
+fn named_class(name: &str) -> Option<fn(&char) -> bool> {
+    let class: fn(&char) -> bool = match name {
+        "alnum" => |c| c.is_alphanumeric(),
+        "alpha" => |c| c.is_alphabetic(),
+        "digit" => |c| c.is_ascii_digit(),
+        "lower" => |c| c.is_lowercase(),
+        "upper" => |c| c.is_uppercase(),
+        "space" => |c| c.is_whitespace(),
+        "punct" => |c| c.is_ascii_punctuation(),
+        "xdigit" => |c| c.is_ascii_hexdigit(),
+        _ => return None,
+    };
+
+    Some(class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).matches(text)
+    }
+
+    #[test]
+    fn stars_backtrack() {
+        assert!(matches("*", ""));
+        assert!(matches("a*b", "ab"));
+        assert!(matches("a*b", "axxbxxb"));
+        assert!(matches("*a*b*", "xaybz"));
+        assert!(matches("*.tar.gz", "x.tar.tar.gz"));
+        assert!(!matches("a*b", "axxbx"));
+        assert!(!matches("*a*b", "bba"));
+        assert!(matches("?*?", "ab"));
+        assert!(!matches("?*?", "a"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc]", "b"));
+        assert!(!matches("[abc]", "d"));
+        assert!(matches("[!abc]", "d"));
+        assert!(matches("[^abc]", "d"));
+        assert!(!matches("[!abc]", "a"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[!]a]", "b"));
+        assert!(!matches("[!]a]", "]"));
+    }
+
+    #[test]
+    fn named_classes() {
+        assert!(matches("[[:digit:]][[:alpha:]]", "1a"));
+        assert!(!matches("[[:digit:]]", "a"));
+        assert!(matches("[![:space:]]", "x"));
+        assert!(!matches("[![:space:]]", " "));
+        assert!(matches("[[:upper:]_]", "_"));
+    }
+
+    #[test]
+    fn escaped_metacharacters_match_literally() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "a"));
+        assert!(matches(r"a\?", "a?"));
+        assert!(!matches(r"a\?", "ab"));
+        assert!(matches(r"\[a]", "[a]"));
+        assert!(matches(r"[\]]", "]"));
+        assert!(matches("[a", "[a"));
+        assert_eq!(Pattern::new(r"a\*b").as_literal().as_deref(), Some("a*b"));
+        assert_eq!(Pattern::new("a*b").as_literal(), None);
+    }
+
+    #[test]
+    fn only_a_leading_dot_matches_hidden_files() {
+        assert!(Pattern::new(".*").matches_hidden());
+        assert!(!Pattern::new("*").matches_hidden());
+        assert!(!Pattern::new("?x").matches_hidden());
+        assert!(!Pattern::new("[.]x").matches_hidden());
+    }
+}
@@ -0,0 +1,62 @@
+use nix::unistd::{getuid, User};
+
+use crate::context::ShellContext;
+
+/// Splits a leading `~` or `~user` off of `s`, returning the home directory
+/// it stands for along with the rest of `s`. A `~` that doesn't name a known
+/// user is left alone.
+pub fn expand<'a>(s: &'a str, ctx: &ShellContext) -> Option<(String, &'a str)> {
+    let rest = s.strip_prefix('~')?;
+    let end = rest.find('/').unwrap_or(rest.len());
+    let (user, rest) = rest.split_at(end);
+
+    let home = if user.is_empty() {
+        match ctx.var("HOME") {
+            Some(home) => home,
+            None => home_dir(User::from_uid(getuid()))?,
+        }
+    } else if user
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        home_dir(User::from_name(user))?
+    } else {
+        return None;
+    };
+
+    Some((home, rest))
+}
+
+fn home_dir(user: nix::Result<Option<User>>) -> Option<String> {
+    Some(user.ok()??.dir.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_comes_from_the_home_variable() {
+        let ctx = ShellContext::default();
+        ctx.set_var("HOME", "/home/me");
+
+        assert_eq!(expand("~", &ctx), Some((String::from("/home/me"), "")));
+        assert_eq!(
+            expand("~/a/b", &ctx),
+            Some((String::from("/home/me"), "/a/b"))
+        );
+        assert_eq!(expand("a~", &ctx), None);
+    }
+
+    #[test]
+    fn users_are_looked_up() {
+        let ctx = ShellContext::default();
+
+        assert_eq!(
+            expand("~root/x", &ctx),
+            Some((home_dir(User::from_name("root")).unwrap(), "/x"))
+        );
+        assert_eq!(expand("~no-such-user-here", &ctx), None);
+        assert_eq!(expand("~$x", &ctx), None);
+    }
+}
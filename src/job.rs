@@ -0,0 +1,421 @@
+use std::{fmt, future::poll_fn, task::Poll};
+
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::sync::oneshot;
+
+use crate::{
+    cmd::delegate::{DelegateCommand, DelegateMessage, ExecutionDelegate},
+    process::{status::VashExitStatus, VashProcess},
+};
+
+/// The status of a job that was killed by a signal, as if that signal was
+/// `SIGTERM`.
+pub const TERMINATED: i32 = 128 + Signal::SIGTERM as i32;
+
+/// A command started with `&`, on its way from wherever it was run to the
+/// shell's job table.
+pub struct BackgroundJob {
+    pub process: VashProcess,
+    /// The command as it was written, without the `&`.
+    pub command: String,
+}
+
+/// Something the job table, which only the shell itself can touch, is asked
+/// to do.
+pub enum JobRequest {
+    Spawn(Box<BackgroundJob>),
+    /// Runs a builtin's function on the table, see
+    /// [`ShellContext::with_jobs`](crate::context::ShellContext::with_jobs).
+    Run(Box<dyn FnOnce(&mut JobTable) + Send>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "Running"),
+            Self::Stopped => write!(f, "Stopped"),
+        }
+    }
+}
+
+/// A background job that is still running.
+pub struct Job {
+    /// The number the job is referred to by, which is the lowest one that
+    /// is higher than that of every other job when it is started.
+    pub id: usize,
+    /// The process id of the job's last command, if that is a real process.
+    pub pid: Option<u32>,
+    pub command: String,
+    pub state: JobState,
+    pub delegate: ExecutionDelegate,
+    /// Told the job's exit status once it finishes, for `wait`.
+    pub waiters: Vec<oneshot::Sender<i32>>,
+}
+
+impl Job {
+    /// Sends `signal` to the job. Only a job that is a real process can be
+    /// stopped or continued, since an adhoc process has nothing to pause.
+    pub fn signal(&mut self, signal: Signal) -> Result<(), String> {
+        let state = match signal {
+            Signal::SIGSTOP | Signal::SIGTSTP | Signal::SIGTTIN | Signal::SIGTTOU => {
+                Some(JobState::Stopped)
+            }
+            Signal::SIGCONT => Some(JobState::Running),
+            _ => None,
+        };
+
+        match (self.pid, state) {
+            (Some(pid), Some(state)) => {
+                kill(Pid::from_raw(pid as i32), signal)
+                    .map_err(|err| format!("%{}: {}", self.id, err.desc()))?;
+                self.state = state;
+            }
+            (None, Some(_)) => {
+                return Err(format!(
+                    "%{}: cannot send {} to a job that isn't a single process",
+                    self.id,
+                    signal.as_str()
+                ))
+            }
+            (_, None) => {
+                self.delegate.tx.send(DelegateCommand::Signal(signal)).ok();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Passes the job's exit status on to everything waiting for it.
+    pub fn finish(&mut self, status: i32) {
+        for waiter in self.waiters.drain(..) {
+            waiter.send(status).ok();
+        }
+    }
+}
+
+/// Something that happened to one of the jobs in the table.
+pub enum JobEvent {
+    Output(Vec<u8>),
+    /// A job finished, with the line announcing it.
+    Finished(String),
+}
+
+/// The jobs running in the background, referred to by `%n` job specs.
+#[derive(Default)]
+pub struct JobTable {
+    /// Ordered by id.
+    jobs: Vec<Job>,
+    /// Job ids from least to most recently started, stopped or continued.
+    /// The last is the current job (`%+`) and the one before it is the
+    /// previous job (`%-`).
+    recent: Vec<usize>,
+    /// Jobs that were removed from the table with `disown`, which are left
+    /// to run without being waited for.
+    disowned: Vec<ExecutionDelegate>,
+}
+
+impl JobTable {
+    /// Adds a new job, returning the id it was given.
+    pub fn add(&mut self, command: String, pid: Option<u32>, delegate: ExecutionDelegate) -> usize {
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+
+        self.jobs.push(Job {
+            id,
+            pid,
+            command,
+            state: JobState::Running,
+            delegate,
+            waiters: Vec::new(),
+        });
+        self.recent.push(id);
+
+        id
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn by_pid(&self, pid: u32) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.pid == Some(pid))
+            .map(|job| job.id)
+    }
+
+    /// Finds the id of the job `spec` refers to, which is one of `%n`, `%%`
+    /// or `%+` for the current job, `%-` for the previous job, `%name` for
+    /// the job whose command starts with `name` and `%?name` for the job
+    /// whose command contains it. The `%` is optional before a number.
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+
+        let matching = |matches: &dyn Fn(&Job) -> bool| {
+            let mut found = self.jobs.iter().filter(|job| matches(job));
+
+            match (found.next(), found.next()) {
+                (Some(job), None) => Ok(job.id),
+                (Some(_), Some(_)) => Err(format!("{}: ambiguous job spec", spec)),
+                (None, _) => Err(no_such_job()),
+            }
+        };
+
+        match spec.strip_prefix('%').unwrap_or(spec) {
+            "" | "%" | "+" => self
+                .recent
+                .last()
+                .copied()
+                .ok_or_else(|| String::from("current: no such job")),
+            "-" => self
+                .recent
+                .iter()
+                .nth_back(1)
+                .copied()
+                .ok_or_else(no_such_job),
+            id if id.bytes().all(|c| c.is_ascii_digit()) => id
+                .parse()
+                .ok()
+                .filter(|id| self.jobs.iter().any(|job| job.id == *id))
+                .ok_or_else(no_such_job),
+            _ if !spec.starts_with('%') => Err(no_such_job()),
+            name => match name.strip_prefix('?') {
+                Some(name) => matching(&|job| job.command.contains(name)),
+                None => matching(&|job| job.command.starts_with(name)),
+            },
+        }
+    }
+
+    /// Makes the job with `id` the current job.
+    pub fn make_current(&mut self, id: usize) {
+        self.recent.retain(|recent| *recent != id);
+        self.recent.push(id);
+    }
+
+    /// Takes the job with `id` out of the table, for when something else is
+    /// going to wait for it.
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        self.recent.retain(|recent| *recent != id);
+
+        Some(self.jobs.remove(index))
+    }
+
+    /// Removes the job with `id` from the table but leaves it running.
+    pub fn disown(&mut self, id: usize) {
+        if let Some(job) = self.remove(id) {
+            self.disowned.push(job.delegate);
+        }
+    }
+
+    /// Describes the job the way `jobs` lists it, like
+    /// `[1]+  Running                 sleep 10 &`. `pid` adds its process id
+    /// after the number.
+    pub fn describe(&self, job: &Job, status: &str, pid: bool) -> String {
+        let marker = match self.recent.iter().rev().position(|id| *id == job.id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        };
+
+        let pid = match (pid, job.pid) {
+            (true, Some(pid)) => format!("{} ", pid),
+            (true, None) => String::from("- "),
+            (false, _) => String::from(" "),
+        };
+
+        let background = if status == "Running" { " &" } else { "" };
+
+        format!(
+            "[{}]{} {}{:<24}{}{}",
+            job.id, marker, pid, status, job.command, background
+        )
+    }
+
+    /// Waits for something to happen to any job, which never finishes if
+    /// there aren't any.
+    pub async fn next(&mut self) -> JobEvent {
+        loop {
+            let (index, disowned, msg) = poll_fn(|cx| {
+                let jobs = self.jobs.iter_mut().map(|job| &mut job.delegate);
+                let disowned = self.disowned.iter_mut();
+
+                for (index, delegate) in jobs.enumerate() {
+                    if let Poll::Ready(msg) = delegate.rx.poll_recv(cx) {
+                        return Poll::Ready((index, false, msg));
+                    }
+                }
+
+                for (index, delegate) in disowned.enumerate() {
+                    if let Poll::Ready(msg) = delegate.rx.poll_recv(cx) {
+                        return Poll::Ready((index, true, msg));
+                    }
+                }
+
+                Poll::Pending
+            })
+            .await;
+
+            let status = match msg {
+                Some(DelegateMessage::Stdout(data) | DelegateMessage::Stderr(data)) => {
+                    return JobEvent::Output(data)
+                }
+                Some(DelegateMessage::Exit(status)) => Some(status),
+                // the job was cancelled rather than exiting by itself
+                Some(DelegateMessage::Error(_)) | None => None,
+            };
+
+            if disowned {
+                self.disowned.remove(index);
+                continue;
+            }
+
+            let line = self.describe(&self.jobs[index], &finished(status), false);
+
+            let id = self.jobs[index].id;
+            let mut job = self.remove(id).unwrap();
+            job.finish(status.map_or(TERMINATED, |status| status.status_code()));
+
+            return JobEvent::Finished(format!("{}\n", line));
+        }
+    }
+}
+
+/// How a finished job is described when it is announced, which is `None` if
+/// it was cancelled.
+fn finished(status: Option<VashExitStatus>) -> String {
+    let Some(status) = status else {
+        return String::from("Terminated");
+    };
+
+    match (status.code(), status.signal().map(Signal::try_from)) {
+        (Some(0), _) => String::from("Done"),
+        (Some(code), _) => format!("Exit {}", code),
+        (None, Some(Ok(Signal::SIGKILL))) => String::from("Killed"),
+        (None, Some(Ok(Signal::SIGINT))) => String::from("Interrupt"),
+        (None, Some(Ok(Signal::SIGHUP))) => String::from("Hangup"),
+        (None, _) => String::from("Terminated"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    use crate::{prelude::*, process::status::BuiltinExitStatus};
+
+    use super::*;
+
+    fn table(commands: &[&str]) -> JobTable {
+        let mut table = JobTable::default();
+
+        for command in commands {
+            let (tx, _) = unbounded_channel();
+            let (_, rx) = unbounded_channel();
+            table.add(command.to_string(), None, ExecutionDelegate { tx, rx });
+        }
+
+        table
+    }
+
+    #[test]
+    fn job_specs() {
+        let mut table = table(&["sleep 10", "sleep 20", "vim notes.txt"]);
+
+        assert_eq!(table.find("%1"), Ok(1));
+        assert_eq!(table.find("2"), Ok(2));
+        assert_eq!(table.find("%%"), Ok(3));
+        assert_eq!(table.find("%+"), Ok(3));
+        assert_eq!(table.find("%"), Ok(3));
+        assert_eq!(table.find("%-"), Ok(2));
+        assert_eq!(table.find("%vim"), Ok(3));
+        assert_eq!(table.find("%?notes"), Ok(3));
+        assert_eq!(table.find("%?10"), Ok(1));
+
+        table.make_current(1);
+        assert_eq!(table.find("%+"), Ok(1));
+        assert_eq!(table.find("%-"), Ok(3));
+    }
+
+    #[test]
+    fn bad_job_specs() {
+        let table = table(&["sleep 10", "sleep 20"]);
+
+        assert_eq!(
+            table.find("%sleep"),
+            Err(String::from("%sleep: ambiguous job spec"))
+        );
+        assert_eq!(
+            table.find("%?0"),
+            Err(String::from("%?0: ambiguous job spec"))
+        );
+        assert_eq!(table.find("%3"), Err(String::from("%3: no such job")));
+        assert_eq!(table.find("%vim"), Err(String::from("%vim: no such job")));
+        assert_eq!(table.find("%?vim"), Err(String::from("%?vim: no such job")));
+        assert_eq!(table.find("sleep"), Err(String::from("sleep: no such job")));
+
+        let empty = JobTable::default();
+        assert_eq!(empty.find("%%"), Err(String::from("current: no such job")));
+        assert_eq!(empty.find("%-"), Err(String::from("%-: no such job")));
+    }
+
+    #[test]
+    fn descriptions() {
+        let mut table = table(&["sleep 10", "sleep 20", "vim notes.txt"]);
+        table.get_mut(3).unwrap().pid = Some(42);
+
+        let describe = |id, status: &str, pid| table.describe(table.get(id).unwrap(), status, pid);
+
+        assert_eq!(
+            describe(3, "Running", false),
+            "[3]+  Running                 vim notes.txt &"
+        );
+        assert_eq!(
+            describe(3, "Stopped", true),
+            "[3]+ 42 Stopped                 vim notes.txt"
+        );
+        assert_eq!(
+            describe(2, "Running", true),
+            "[2]- - Running                 sleep 20 &"
+        );
+        assert_eq!(
+            describe(1, "Done", false),
+            "[1]   Done                    sleep 10"
+        );
+    }
+
+    #[test]
+    fn finished_jobs() {
+        let exited = |code: i32| Some(VashExitStatus::from(ExitStatus::from_raw(code << 8)));
+        let killed = |signal| Some(VashExitStatus::from(ExitStatus::from_raw(signal)));
+
+        assert_eq!(finished(exited(0)), "Done");
+        assert_eq!(finished(exited(3)), "Exit 3");
+        assert_eq!(
+            finished(Some(BuiltinExitStatus::new_failure().into())),
+            "Exit 1"
+        );
+        assert_eq!(finished(killed(Signal::SIGKILL as i32)), "Killed");
+        assert_eq!(finished(killed(Signal::SIGTERM as i32)), "Terminated");
+        assert_eq!(finished(None), "Terminated");
+
+        assert_eq!(killed(Signal::SIGKILL as i32).unwrap().status_code(), 137);
+        assert_eq!(exited(3).unwrap().status_code(), 3);
+    }
+}
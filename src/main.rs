@@ -11,14 +11,22 @@ use termion::{
 use tokio::select;
 use tracing_subscriber::prelude::*;
 
-use crate::state::{Direction, State};
+use crate::{
+    context::ShellContext,
+    job::JobTable,
+    prelude::*,
+    state::{Direction, State},
+};
 
 #[macro_use]
 extern crate tracing;
 
 pub mod builtins;
 pub mod cmd;
+pub mod context;
+pub mod expand;
 pub mod input;
+pub mod job;
 pub mod parse;
 pub mod prelude;
 pub mod process;
@@ -73,16 +81,24 @@ async fn main() -> Result<()> {
     trace!("spawning input thread");
     let mut rx = input::spawn_input_thread().await;
 
+    let (job_sender, job_requests) = unbounded_channel();
+    let context = ShellContext::from_env();
+    context.set_job_sender(job_sender);
+
     let mut state = State {
         prompt: "vash> ".into(),
         input: String::new(),
+        pending: None,
         history: Vec::new(),
         history_pos: 0,
         output: String::new(),
         running: None,
-        working_dir: std::env::current_dir()?,
+        jobs: JobTable::default(),
+        job_requests,
         scroll_y: 0,
         scrolled_when_len: None,
+        context,
+        exit: None,
     };
 
     trace!("rendering initial state");
@@ -93,7 +109,7 @@ async fn main() -> Result<()> {
             Some(msg) = rx.recv() => match msg {
                 input::InputMessage::Key(key) => match key {
                     Key::Char('\n') => {
-                        state.execute().await?;
+                        state.execute().await;
                     }
                     Key::Char(c) => {
                         state.push_char(c);
@@ -108,7 +124,7 @@ async fn main() -> Result<()> {
                         state.mutate_history_pos(Direction::Down);
                     }
                     Key::Ctrl('c') => {
-                        if !state.terminate() {
+                        if !state.terminate() && !state.cancel_pending() {
                             break;
                         }
                     }
@@ -138,11 +154,21 @@ async fn main() -> Result<()> {
         }
 
         state.render(&mut term().lock())?;
+
+        if state.exit.is_some() {
+            break;
+        }
     }
 
     let stdout = unsafe { std::mem::take(TERMINAL.get_mut().unwrap()) }.unwrap();
 
     drop(stdout);
 
+    if let Some(status) = state.exit {
+        // nothing is dropped past this point, so flush the logs first
+        drop(_guard);
+        std::process::exit(status);
+    }
+
     Ok(())
 }
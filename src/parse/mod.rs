@@ -1,88 +1,169 @@
-use logos::Logos;
 use thiserror::Error;
 
 use crate::cmd::execution_plan::ExecutionPlan;
 
-use self::token::{LexerError, Token};
+use self::{parser::Parser, token::LexerError};
 
+pub mod parser;
+pub mod scan;
 pub mod token;
 pub mod unescape;
+pub mod word;
+
+pub use logos::Span;
+
+/// A command that could not be parsed, along with the part of the source
+/// that caused it.
+#[derive(Debug, Error)]
+#[error("{kind}")]
+pub struct CommandParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
 
 #[derive(Debug, Error)]
-pub enum CommandParseError {
-    #[error("failed to tokenize command")]
-    Lexer(Vec<LexerError>),
+pub enum ParseErrorKind {
+    #[error("{0}")]
+    Lexer(#[from] LexerError),
+    #[error("unexpected token `{0}`")]
+    UnexpectedToken(String),
+    #[error("unsupported file descriptor {0}")]
+    UnsupportedDescriptor(u32),
+    #[error("heredoc is missing its `{0}` delimiter")]
+    UnterminatedHereDoc(String),
+    #[error("unexpected end of input")]
+    UnexpectedEof,
+    #[error("empty command")]
+    Empty,
+}
+
+impl CommandParseError {
+    pub fn new(kind: impl Into<ParseErrorKind>, span: Span) -> Self {
+        Self {
+            kind: kind.into(),
+            span,
+        }
+    }
+
+    /// Whether the source just ended too early, meaning more input could
+    /// still make it valid, such as an unclosed quote or a trailing `|`.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self.kind,
+            ParseErrorKind::UnexpectedEof
+                | ParseErrorKind::UnterminatedHereDoc(_)
+                | ParseErrorKind::Lexer(
+                    LexerError::UnterminatedString
+                        | LexerError::UnterminatedSubstitution
+                        | LexerError::TrailingBackslash
+                )
+        )
+    }
+
+    /// Renders the error under the line of `source` it occurred on, with
+    /// carets pointing at the offending part:
+    ///
+    /// ```text
+    /// echo "hello
+    ///      ^^^^^^ unterminated string
+    /// ```
+    pub fn diagnostic(&self, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let end = self.span.end.clamp(start, source.len());
+
+        let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |idx| start + idx);
+        let line = &source[line_start..line_end];
+
+        let column = source[line_start..start].chars().count();
+        let width = source[start..end.min(line_end)].chars().count().max(1);
+
+        format!(
+            "{}\n{}{} {}\n",
+            line,
+            " ".repeat(column),
+            "^".repeat(width),
+            self
+        )
+    }
 }
 
 pub fn parse_command(cmd: &str) -> Result<ExecutionPlan, CommandParseError> {
-    let tokens = Token::lexer(cmd);
+    Parser::new(cmd).parse()
+}
 
-    let tokens = tokens.collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if tokens.iter().any(|r| r.is_err()) {
-        return Err(CommandParseError::Lexer(
-            tokens.into_iter().filter_map(|r| r.err()).collect(),
-        ));
+    fn incomplete(source: &str) -> bool {
+        match parse_command(source) {
+            Ok(_) => false,
+            Err(err) => err.is_incomplete(),
+        }
     }
 
-    let tokens = tokens.into_iter().map(|r| r.unwrap()).collect::<Vec<_>>();
-
-    let mut current_cmd = Vec::new();
-    let mut incomplete = None::<IncompleteOperator>;
-
-    for token in tokens {
-        match token {
-            Token::Comment(_) => continue,
-            Token::Identifier(seg) => {
-                current_cmd.push(seg.to_owned());
-            }
-            Token::DoubleQuotedString(seg) | Token::SingleQuotedString(seg) => {
-                current_cmd.push(seg);
-            }
-            Token::And => {
-                incomplete = Some(IncompleteOperator::And(complete(
-                    &mut current_cmd,
-                    &mut incomplete,
-                )))
-            }
-            Token::Or => {
-                incomplete = Some(IncompleteOperator::Or(complete(
-                    &mut current_cmd,
-                    &mut incomplete,
-                )))
-            }
-            Token::Pipe => {
-                incomplete = Some(IncompleteOperator::Pipe(complete(
-                    &mut current_cmd,
-                    &mut incomplete,
-                )))
-            }
-            _ => todo!("implement token {:?}", token),
+    #[test]
+    fn unfinished_commands_are_incomplete() {
+        for source in [
+            "echo \"hello",
+            "echo 'hello",
+            "echo $'hello",
+            "echo $(date",
+            "echo ${x:-\"}\"",
+            "echo `date",
+            "a |",
+            "a &&",
+            "a ||\n",
+            "if true; then",
+            "if true; then a; else",
+            "while true; do a",
+            "for x in a b",
+            "case x in a)",
+            "{ a;",
+            "( a",
+            "f() {",
+            "cat <<EOF\nhello",
+            "echo a \\",
+            "echo a\\",
+            "echo a\\\\\\",
+        ] {
+            assert!(incomplete(source), "{:?} should be incomplete", source);
         }
     }
 
-    if !current_cmd.is_empty() {
-        Ok(complete(&mut current_cmd, &mut incomplete))
-    } else {
-        Err(CommandParseError::Lexer(Vec::new()))
+    #[test]
+    fn finished_commands_parse() {
+        for source in [
+            "echo \"hello\nworld\"",
+            "a |\nb",
+            "if true; then\na\nfi",
+            "for x in a b\ndo a; done",
+            "cat <<EOF\nhello\nEOF",
+            "echo a \\\nb",
+            "echo a\\\\",
+        ] {
+            assert!(parse_command(source).is_ok(), "{:?} should parse", source);
+        }
     }
-}
 
-pub enum IncompleteOperator {
-    And(ExecutionPlan),
-    Or(ExecutionPlan),
-    Pipe(ExecutionPlan),
-}
+    #[test]
+    fn errors_are_not_incomplete() {
+        for source in ["a )", "fi", "a && ; b", "do a; done"] {
+            let err = parse_command(source).unwrap_err();
+            assert!(!err.is_incomplete(), "{:?}: {:?}", source, err);
+        }
+    }
 
-fn complete(cmd: &mut Vec<String>, incomplete: &mut Option<IncompleteOperator>) -> ExecutionPlan {
-    let binary = cmd.remove(0);
-    let args = std::mem::take(cmd);
+    #[test]
+    fn diagnostic_points_at_the_error() {
+        let err = parse_command("echo ok\na )").unwrap_err();
 
-    let cmd = ExecutionPlan::Execute(binary, args);
-    match std::mem::take(incomplete) {
-        Some(IncompleteOperator::And(left)) => ExecutionPlan::And(Box::new(left), Box::new(cmd)),
-        Some(IncompleteOperator::Or(left)) => ExecutionPlan::Or(Box::new(left), Box::new(cmd)),
-        Some(IncompleteOperator::Pipe(left)) => ExecutionPlan::Pipe(Box::new(left), Box::new(cmd)),
-        None => cmd,
+        assert_eq!(
+            err.diagnostic("echo ok\na )"),
+            "a )\n  ^ unexpected token `)`\n"
+        );
     }
 }
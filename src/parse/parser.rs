@@ -0,0 +1,1185 @@
+use logos::{Lexer, Logos};
+
+use crate::cmd::execution_plan::{
+    ArithmeticCommand, Assignment, BackgroundCommand, CaseClause, CaseItem, CaseTerminator,
+    CommandGroup, ExecutionPlan, ForClause, FunctionDefinition, HereDoc, IfClause, PipeRedirection,
+    PipeType, SimpleCommand, WhileClause,
+};
+
+use super::{
+    scan,
+    token::{LexerError, Token},
+    word::{is_name, Quoting, Word},
+    CommandParseError, ParseErrorKind, Span,
+};
+
+type Spanned<'a> = (Token<'a>, Span);
+
+/// A recursive-descent parser over the token stream of a single command line.
+///
+/// The grammar, from lowest to highest precedence:
+///
+/// ```text
+/// list         := linebreak and_or (separator and_or)* separator?
+/// separator    := (";" | "&") linebreak | newline+
+/// and_or       := pipeline (("&&" | "||") linebreak pipeline)*
+/// pipeline     := command ("|" linebreak command)*
+/// command      := compound_command redirection* | function_definition | simple_command
+/// compound_command := if_clause | while_clause | for_clause | case_clause
+///                 | arithmetic_command | brace_group | subshell
+/// if_clause    := "if" list "then" list ("elif" list "then" list)*
+///                 ("else" list)? "fi"
+/// while_clause := ("while" | "until") list do_group
+/// for_clause   := "for" name linebreak ("in" word* separator | ";")? linebreak do_group
+/// do_group     := "do" list "done"
+/// function_definition := ("function" name ("(" ")")? | name "(" ")") linebreak
+///                 compound_command redirection*
+/// brace_group  := "{" list "}"
+/// subshell     := "(" list ")"
+/// arithmetic_command := "((" expression "))"
+/// case_clause  := "case" word linebreak "in" linebreak case_item* "esac"
+/// case_item    := "("? word ("|" word)* ")" list? (";;" | ";&" | ";;&")? linebreak
+/// simple_command := assignment* (word | redirection)+ | assignment+
+/// redirection  := ("<" | ">" | ">>" | "&>" | "&>>" | "<<<") word | ">&" fd
+///               | ("<<" | "<<-") word
+/// ```
+///
+/// Heredoc bodies are read straight from the source, starting at the line
+/// after the operator, and the lexer is moved past them once it reaches the
+/// end of that line.
+///
+/// `&&` and `||` share a precedence level and associate to the left, so
+/// `a | b && c || d` parses as `((a | b) && c) || d`.
+pub struct Parser<'a> {
+    lexer: Lexer<'a, Token<'a>>,
+    peeked: Option<Option<Spanned<'a>>>,
+    /// The source taken up by heredoc bodies belonging to the current line.
+    heredocs: Option<Span>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            lexer: Token::lexer(source),
+            peeked: None,
+            heredocs: None,
+        }
+    }
+
+    pub fn parse(mut self) -> Result<ExecutionPlan, CommandParseError> {
+        self.skip_newlines()?;
+
+        if self.peek()?.is_none() {
+            return Err(CommandParseError::new(ParseErrorKind::Empty, 0..0));
+        }
+
+        let plan = self.parse_list()?;
+
+        match self.next()? {
+            None => Ok(plan),
+            Some((_, span)) => Err(self.unexpected(span)),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        self.skip_newlines()?;
+
+        let mut plans = vec![self.parse_and_or()?];
+
+        while let Some((Token::Semi | Token::Newline | Token::Ampersand, _)) = self.peek()? {
+            if let Some((Token::Ampersand, span)) = self.next()? {
+                let plan = plans.pop().unwrap();
+                let start = plan.span().start;
+
+                plans.push(ExecutionPlan::Background(BackgroundCommand {
+                    source: self.lexer.source()[start..plan.span().end].to_owned(),
+                    plan: Box::new(plan),
+                    span: start..span.end,
+                }));
+            }
+
+            self.skip_newlines()?;
+
+            if !self.starts_command()? {
+                break;
+            }
+
+            plans.push(self.parse_and_or()?);
+        }
+
+        if plans.len() == 1 {
+            Ok(plans.pop().unwrap())
+        } else {
+            Ok(ExecutionPlan::Sequence(plans))
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let mut left = self.parse_pipeline()?;
+
+        loop {
+            match self.peek()? {
+                Some((Token::And, _)) => {
+                    self.next()?;
+                    self.skip_newlines()?;
+                    let right = self.parse_pipeline()?;
+                    left = ExecutionPlan::And(Box::new(left), Box::new(right));
+                }
+                Some((Token::Or, _)) => {
+                    self.next()?;
+                    self.skip_newlines()?;
+                    let right = self.parse_pipeline()?;
+                    left = ExecutionPlan::Or(Box::new(left), Box::new(right));
+                }
+                _ => break Ok(left),
+            }
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let mut left = self.parse_command()?;
+
+        while let Some((Token::Pipe, _)) = self.peek()? {
+            self.next()?;
+            self.skip_newlines()?;
+            let right = self.parse_command()?;
+            left = ExecutionPlan::Pipe(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_command(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        if self.at_function_definition()? {
+            return self.parse_function();
+        }
+
+        let arithmetic = self.at_arithmetic()?;
+
+        let plan = match self.peek()? {
+            Some((Token::If, _)) => self.parse_if()?,
+            Some((Token::While | Token::Until, _)) => self.parse_while()?,
+            Some((Token::For, _)) => self.parse_for()?,
+            Some((Token::Case, _)) => self.parse_case()?,
+            Some((Token::LParen, _)) if arithmetic => self.parse_arithmetic()?,
+            Some((Token::LParen, _)) => self.parse_subshell()?,
+            Some((Token::Word("{"), _)) => self.parse_brace_group()?,
+            _ => return self.parse_simple_command(),
+        };
+
+        let mut redirections = Vec::new();
+        while self.parse_redirection(&mut redirections)? {}
+
+        Ok(Self::redirect(plan, redirections))
+    }
+
+    fn parse_if(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::If)?.start;
+        let mut branches = Vec::new();
+
+        loop {
+            let condition = self.parse_list()?;
+            self.expect(Token::Then)?;
+            branches.push((condition, self.parse_list()?));
+
+            let (otherwise, end) = match self.next()? {
+                Some((Token::Elif, _)) => continue,
+                Some((Token::Else, _)) => {
+                    let otherwise = self.parse_list()?;
+                    (Some(Box::new(otherwise)), self.expect(Token::Fi)?.end)
+                }
+                Some((Token::Fi, span)) => (None, span.end),
+                Some((_, span)) => return Err(self.unexpected(span)),
+                None => return Err(self.eof()),
+            };
+
+            return Ok(ExecutionPlan::If(IfClause {
+                branches,
+                otherwise,
+                span: start..end,
+            }));
+        }
+    }
+
+    fn parse_simple_command(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirections = Vec::new();
+
+        loop {
+            // a command can't be named after a keyword like `fi`
+            if words.is_empty() && assignments.is_empty() && self.at_closing_keyword()? {
+                break;
+            }
+
+            if let Some(word) = self.word()? {
+                if words.is_empty() {
+                    if let Some(assignment) = Self::assignment(&word) {
+                        assignments.push(assignment);
+                        continue;
+                    }
+                }
+
+                words.push(word);
+            } else if !self.parse_redirection(&mut redirections)? {
+                break;
+            }
+        }
+
+        let plan = if !words.is_empty() || !assignments.is_empty() {
+            let start = assignments
+                .first()
+                .map(|assignment| assignment.span.start)
+                .or_else(|| words.first().map(|word| word.span.start))
+                .unwrap();
+            let end = words
+                .last()
+                .map(|word| word.span.end)
+                .or_else(|| assignments.last().map(|assignment| assignment.span.end))
+                .unwrap();
+
+            ExecutionPlan::Execute(SimpleCommand {
+                assignments,
+                words,
+                span: start..end,
+            })
+        } else if !redirections.is_empty() {
+            ExecutionPlan::NoOp
+        } else {
+            return match self.next()? {
+                Some((_, span)) => Err(self.unexpected(span)),
+                None => Err(self.eof()),
+            };
+        };
+
+        Ok(Self::redirect(plan, redirections))
+    }
+
+    /// Parses `word` as a `NAME=value` assignment.
+    fn assignment(word: &Word) -> Option<Assignment> {
+        let (name, value) = word.assignment()?;
+
+        Some(Assignment {
+            name: name.to_owned(),
+            value,
+            span: word.span.clone(),
+        })
+    }
+
+    /// Wraps `plan` in `redirections`, keeping the first one outermost.
+    fn redirect(plan: ExecutionPlan, redirections: Vec<PipeRedirection>) -> ExecutionPlan {
+        redirections
+            .into_iter()
+            .rev()
+            .fold(plan, |plan, redirection| {
+                ExecutionPlan::RedirectPipe(Box::new(plan), redirection)
+            })
+    }
+
+    /// Consumes a redirection operator and its target if one is next, pushing
+    /// the resulting redirections onto `redirections`.
+    fn parse_redirection(
+        &mut self,
+        redirections: &mut Vec<PipeRedirection>,
+    ) -> Result<bool, CommandParseError> {
+        if !self.at_redirection()? {
+            return Ok(false);
+        }
+
+        let (token, span) = self.next()?.unwrap();
+
+        if let Token::Duplicate((from, to)) = token {
+            redirections.push(PipeRedirection {
+                from: Self::output_descriptor(from, &span)?,
+                to: Self::output_descriptor(to, &span)?,
+                append: false,
+                span,
+            });
+
+            return Ok(true);
+        }
+
+        let target = self.target()?;
+        let span = span.start..target.span.end;
+
+        match token {
+            Token::Write(fd) | Token::Append(fd) => redirections.push(PipeRedirection {
+                from: Self::output_descriptor(fd, &span)?,
+                to: PipeType::File(target),
+                append: matches!(token, Token::Append(_)),
+                span,
+            }),
+            Token::Read(0) => redirections.push(PipeRedirection {
+                from: PipeType::File(target),
+                to: PipeType::Stdin,
+                append: false,
+                span,
+            }),
+            Token::Read(fd) => {
+                return Err(CommandParseError::new(
+                    ParseErrorKind::UnsupportedDescriptor(fd),
+                    span,
+                ))
+            }
+            Token::HereDoc | Token::HereDocStrip => {
+                let source = self.lexer.source();
+                let quoted = source[target.span.clone()].contains(['\'', '"', '\\']);
+                let strip_tabs = matches!(token, Token::HereDocStrip);
+                let body = self.heredoc_body(&target.literal(), strip_tabs, &span)?;
+
+                redirections.push(PipeRedirection {
+                    from: PipeType::HereDoc(HereDoc {
+                        body,
+                        expand: !quoted,
+                    }),
+                    to: PipeType::Stdin,
+                    append: false,
+                    span,
+                })
+            }
+            Token::HereString => redirections.push(PipeRedirection {
+                from: PipeType::HereString(target),
+                to: PipeType::Stdin,
+                append: false,
+                span,
+            }),
+            Token::WriteAll | Token::AppendAll => {
+                redirections.push(PipeRedirection {
+                    from: PipeType::Stdout,
+                    to: PipeType::File(target),
+                    append: matches!(token, Token::AppendAll),
+                    span: span.clone(),
+                });
+                redirections.push(PipeRedirection {
+                    from: PipeType::Stderr,
+                    to: PipeType::Stdout,
+                    append: false,
+                    span,
+                });
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(true)
+    }
+
+    fn output_descriptor(fd: u32, span: &Span) -> Result<PipeType, CommandParseError> {
+        match fd {
+            1 => Ok(PipeType::Stdout),
+            2 => Ok(PipeType::Stderr),
+            fd => Err(CommandParseError::new(
+                ParseErrorKind::UnsupportedDescriptor(fd),
+                span.clone(),
+            )),
+        }
+    }
+
+    /// Reads the body of a heredoc terminated by `delimiter`, which starts on
+    /// the line after the current one or after the previous heredoc's body.
+    /// `span` covers the redirection, for reporting a missing delimiter.
+    fn heredoc_body(
+        &mut self,
+        delimiter: &str,
+        strip_tabs: bool,
+        span: &Span,
+    ) -> Result<String, CommandParseError> {
+        let source = self.lexer.source();
+
+        let start = match &self.heredocs {
+            Some(heredocs) => heredocs.end,
+            None => self.line_end()?,
+        };
+
+        let mut body = String::new();
+        let mut pos = start;
+
+        loop {
+            if pos >= source.len() {
+                return Err(CommandParseError::new(
+                    ParseErrorKind::UnterminatedHereDoc(delimiter.to_owned()),
+                    span.clone(),
+                ));
+            }
+
+            let end = source[pos..]
+                .find('\n')
+                .map(|idx| pos + idx + 1)
+                .unwrap_or(source.len());
+            let line = source[pos..end].trim_end_matches('\n');
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+
+            pos = end;
+
+            if line == delimiter {
+                break;
+            }
+
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        match &mut self.heredocs {
+            Some(heredocs) => heredocs.end = pos,
+            None => self.heredocs = Some(start..pos),
+        }
+
+        // the newline ending this line may have already been peeked, in which
+        // case the lexer is sitting right at the start of the body
+        if let Some(Some((Token::Newline, span))) = &self.peeked {
+            if span.end == start {
+                self.skip_heredocs();
+            }
+        }
+
+        Ok(body)
+    }
+
+    /// The offset just past the newline ending the current line.
+    fn line_end(&mut self) -> Result<usize, CommandParseError> {
+        if let Some(Some((Token::Newline, span))) = &self.peeked {
+            return Ok(span.end);
+        }
+
+        let mut lexer = self.lexer.clone();
+
+        loop {
+            match lexer.next() {
+                Some(Ok(Token::Newline)) => break Ok(lexer.span().end),
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => break Err(Self::lexer_error(&lexer, err)),
+                None => break Ok(lexer.source().len()),
+            }
+        }
+    }
+
+    fn skip_heredocs(&mut self) {
+        if let Some(heredocs) = self.heredocs.take() {
+            self.lexer.bump(heredocs.end - heredocs.start);
+        }
+    }
+
+    /// The word a redirection operator points at.
+    fn target(&mut self) -> Result<Word, CommandParseError> {
+        match self.word()? {
+            Some(word) => Ok(word),
+            None => match self.next()? {
+                Some((_, span)) => Err(self.unexpected(span)),
+                None => Err(self.eof()),
+            },
+        }
+    }
+
+    fn parse_while(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let (until, start) = match self.next()? {
+            Some((Token::While, span)) => (false, span.start),
+            Some((Token::Until, span)) => (true, span.start),
+            _ => unreachable!(),
+        };
+
+        let condition = self.parse_list()?;
+        let (body, end) = self.parse_do_group()?;
+
+        Ok(ExecutionPlan::While(WhileClause {
+            condition: Box::new(condition),
+            body: Box::new(body),
+            until,
+            span: start..end,
+        }))
+    }
+
+    fn parse_for(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::For)?.start;
+
+        // the variable can be named after a keyword, as in `for in in a`
+        let variable = self.target()?;
+        if !is_name(&variable.source()) {
+            return Err(self.unexpected(variable.span));
+        }
+        let variable = variable.source();
+
+        self.skip_newlines()?;
+
+        let words = match self.peek()? {
+            Some((Token::In, _)) => {
+                self.next()?;
+
+                let mut words = Vec::new();
+                while let Some(word) = self.word()? {
+                    words.push(word);
+                }
+
+                match self.next()? {
+                    Some((Token::Semi | Token::Newline, _)) => {}
+                    Some((_, span)) => return Err(self.unexpected(span)),
+                    None => return Err(self.eof()),
+                }
+
+                Some(words)
+            }
+            Some((Token::Semi, _)) => {
+                self.next()?;
+                None
+            }
+            _ => None,
+        };
+
+        self.skip_newlines()?;
+        let (body, end) = self.parse_do_group()?;
+
+        Ok(ExecutionPlan::For(ForClause {
+            variable,
+            words,
+            body: Box::new(body),
+            span: start..end,
+        }))
+    }
+
+    fn parse_case(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::Case)?.start;
+        let word = self.target()?;
+
+        self.skip_newlines()?;
+        self.expect(Token::In)?;
+        self.skip_newlines()?;
+
+        let mut items = Vec::new();
+
+        let end = loop {
+            if let Some((Token::Esac, _)) = self.peek()? {
+                break self.next()?.unwrap().1.end;
+            }
+
+            if let Some((Token::LParen, _)) = self.peek()? {
+                self.next()?;
+            }
+
+            let mut patterns = vec![self.target()?];
+            while let Some((Token::Pipe, _)) = self.peek()? {
+                self.next()?;
+                patterns.push(self.target()?);
+            }
+
+            self.expect(Token::RParen)?;
+            self.skip_newlines()?;
+
+            let body = if self.starts_command()? {
+                self.parse_list()?
+            } else {
+                ExecutionPlan::NoOp
+            };
+
+            // the last item doesn't need a terminator
+            let terminator = match self.next()? {
+                Some((Token::DoubleSemi, _)) => CaseTerminator::Break,
+                Some((Token::SemiAnd, _)) => CaseTerminator::FallThrough,
+                Some((Token::DoubleSemiAnd, _)) => CaseTerminator::Continue,
+                Some((Token::Esac, span)) => {
+                    items.push(CaseItem {
+                        patterns,
+                        body,
+                        terminator: CaseTerminator::Break,
+                    });
+                    break span.end;
+                }
+                Some((_, span)) => return Err(self.unexpected(span)),
+                None => return Err(self.eof()),
+            };
+
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator,
+            });
+
+            self.skip_newlines()?;
+        };
+
+        Ok(ExecutionPlan::Case(CaseClause {
+            word,
+            items,
+            span: start..end,
+        }))
+    }
+
+    fn parse_function(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let keyword = match self.peek()? {
+            Some((Token::Function, span)) => Some(span.start),
+            _ => None,
+        };
+
+        if keyword.is_some() {
+            self.next()?;
+        }
+
+        let (name, span) = match self.next()? {
+            Some((Token::Word(name), span)) if is_function_name(name) => (name.to_owned(), span),
+            Some((_, span)) => return Err(self.unexpected(span)),
+            None => return Err(self.eof()),
+        };
+
+        // the parentheses are optional after `function`
+        if keyword.is_none() || matches!(self.peek()?, Some((Token::LParen, _))) {
+            self.expect(Token::LParen)?;
+            self.expect(Token::RParen)?;
+        }
+
+        self.skip_newlines()?;
+
+        let body = match self.peek()? {
+            Some((
+                Token::Word("{")
+                | Token::LParen
+                | Token::If
+                | Token::While
+                | Token::Until
+                | Token::For
+                | Token::Case,
+                _,
+            )) => self.parse_command()?,
+            Some((_, span)) => {
+                let span = span.clone();
+                return Err(self.unexpected(span));
+            }
+            None => return Err(self.eof()),
+        };
+
+        Ok(ExecutionPlan::Function(FunctionDefinition {
+            name,
+            span: keyword.unwrap_or(span.start)..body.span().end,
+            body: Box::new(body),
+        }))
+    }
+
+    /// Whether a function definition is next, which for `name() { ... }`
+    /// means looking past the name for the `(`.
+    fn at_function_definition(&mut self) -> Result<bool, CommandParseError> {
+        Ok(match self.peek()? {
+            Some((Token::Function, _)) => true,
+            Some((Token::Word(name), _)) if *name != "{" => {
+                matches!(self.lexer.clone().next(), Some(Ok(Token::LParen)))
+            }
+            _ => false,
+        })
+    }
+
+    /// Parses `{ list }`, where the braces are only special at the start of
+    /// a command.
+    fn parse_brace_group(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::Word("{"))?.start;
+        let body = self.parse_list()?;
+        let end = self.expect(Token::Word("}"))?.end;
+
+        Ok(ExecutionPlan::BraceGroup(CommandGroup {
+            body: Box::new(body),
+            span: start..end,
+        }))
+    }
+
+    /// Parses `( list )`.
+    fn parse_subshell(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::LParen)?.start;
+        let body = self.parse_list()?;
+        let end = self.expect(Token::RParen)?.end;
+
+        Ok(ExecutionPlan::Subshell(CommandGroup {
+            body: Box::new(body),
+            span: start..end,
+        }))
+    }
+
+    /// Whether the `(` that was just peeked is the start of `((`.
+    fn at_arithmetic(&mut self) -> Result<bool, CommandParseError> {
+        let end = match self.peek()? {
+            Some((Token::LParen, span)) => span.end,
+            _ => return Ok(false),
+        };
+
+        let rest = &self.lexer.source()[end..];
+
+        Ok(rest.starts_with('(') && scan::arithmetic_end(&rest[1..]).is_some())
+    }
+
+    /// Parses `(( expression ))`, taking the expression straight from the
+    /// source since it is made of arithmetic rather than words.
+    fn parse_arithmetic(&mut self) -> Result<ExecutionPlan, CommandParseError> {
+        let start = self.expect(Token::LParen)?.start;
+
+        let source = self.lexer.source();
+        let from = start + 2;
+        let len = scan::arithmetic_end(&source[from..]).unwrap();
+
+        // the lexer is sitting right after the first `(`
+        self.lexer.bump(1 + len + 2);
+
+        Ok(ExecutionPlan::Arithmetic(ArithmeticCommand {
+            expression: source[from..from + len].to_owned(),
+            span: start..from + len + 2,
+        }))
+    }
+
+    /// Parses `do list done`, returning the list and where `done` ends.
+    fn parse_do_group(&mut self) -> Result<(ExecutionPlan, usize), CommandParseError> {
+        self.expect(Token::Do)?;
+        let body = self.parse_list()?;
+        let end = self.expect(Token::Done)?.end;
+
+        Ok((body, end))
+    }
+
+    /// Consumes the next token, which has to be `expected`.
+    fn expect(&mut self, expected: Token<'a>) -> Result<Span, CommandParseError> {
+        match self.next()? {
+            Some((token, span)) if token == expected => Ok(span),
+            Some((_, span)) => Err(self.unexpected(span)),
+            None => Err(self.eof()),
+        }
+    }
+
+    /// Whether the next token can begin a command.
+    fn starts_command(&mut self) -> Result<bool, CommandParseError> {
+        if self.at_closing_keyword()? {
+            return Ok(false);
+        }
+
+        Ok(self.at_word()?
+            || self.at_redirection()?
+            || matches!(
+                self.peek()?,
+                Some((
+                    Token::If
+                        | Token::While
+                        | Token::Until
+                        | Token::For
+                        | Token::Case
+                        | Token::Function
+                        | Token::LParen,
+                    _
+                ))
+            ))
+    }
+
+    fn at_redirection(&mut self) -> Result<bool, CommandParseError> {
+        Ok(matches!(
+            self.peek()?,
+            Some((
+                Token::Write(_)
+                    | Token::Append(_)
+                    | Token::Read(_)
+                    | Token::Duplicate(_)
+                    | Token::WriteAll
+                    | Token::AppendAll
+                    | Token::HereDoc
+                    | Token::HereDocStrip
+                    | Token::HereString,
+                _
+            ))
+        ))
+    }
+
+    /// Whether the next token is a keyword that ends the list before it, like
+    /// `then` or `done`. These are only keywords where a command could start,
+    /// anywhere else they are just words.
+    fn at_closing_keyword(&mut self) -> Result<bool, CommandParseError> {
+        Ok(matches!(
+            self.peek()?,
+            Some((
+                Token::Then
+                    | Token::Elif
+                    | Token::Else
+                    | Token::Fi
+                    | Token::Do
+                    | Token::Done
+                    | Token::Esac
+                    | Token::In
+                    | Token::Word("}"),
+                _
+            ))
+        ))
+    }
+
+    fn at_word(&mut self) -> Result<bool, CommandParseError> {
+        Ok(matches!(
+            self.peek()?,
+            Some((
+                Token::Word(_)
+                    | Token::Number(_)
+                    | Token::If
+                    | Token::Then
+                    | Token::Elif
+                    | Token::Else
+                    | Token::Fi
+                    | Token::While
+                    | Token::Until
+                    | Token::Do
+                    | Token::Done
+                    | Token::For
+                    | Token::In
+                    | Token::Function
+                    | Token::Case
+                    | Token::Esac
+                    | Token::Break
+                    | Token::Continue,
+                _
+            ))
+        ))
+    }
+
+    /// Consumes the next token if it is a word.
+    fn word(&mut self) -> Result<Option<Word>, CommandParseError> {
+        if !self.at_word()? {
+            return Ok(None);
+        }
+
+        let word = match self.next()? {
+            Some((Token::Word(source), span)) => Word::parse(source, span.clone())
+                .map_err(|err| CommandParseError::new(err, span))?,
+            // keywords are plain words outside of command position, and
+            // `break` and `continue` are builtins that only have tokens of
+            // their own so the lexer knows about them
+            Some((_, span)) => {
+                Word::new(&self.lexer.source()[span.clone()], Quoting::Unquoted, span)
+            }
+            None => unreachable!(),
+        };
+
+        Ok(Some(word))
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), CommandParseError> {
+        while let Some((Token::Newline, _)) = self.peek()? {
+            self.next()?;
+        }
+
+        Ok(())
+    }
+
+    fn peek(&mut self) -> Result<Option<&Spanned<'a>>, CommandParseError> {
+        if self.peeked.is_none() {
+            let next = self.lex()?;
+            self.peeked = Some(next);
+        }
+
+        Ok(self.peeked.as_ref().unwrap().as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<Spanned<'a>>, CommandParseError> {
+        match self.peeked.take() {
+            Some(peeked) => Ok(peeked),
+            None => self.lex(),
+        }
+    }
+
+    fn lex(&mut self) -> Result<Option<Spanned<'a>>, CommandParseError> {
+        loop {
+            match self.lexer.next() {
+                Some(Ok(Token::Comment(_))) => continue,
+                Some(Ok(Token::Newline)) => {
+                    let span = self.lexer.span();
+
+                    if matches!(&self.heredocs, Some(heredocs) if heredocs.start == span.end) {
+                        self.skip_heredocs();
+                    }
+
+                    break Ok(Some((Token::Newline, span)));
+                }
+                Some(Ok(token)) => break Ok(Some((token, self.lexer.span()))),
+                Some(Err(err)) => break Err(Self::lexer_error(&self.lexer, err)),
+                None => break Ok(None),
+            }
+        }
+    }
+
+    fn unexpected(&self, span: Span) -> CommandParseError {
+        let token = self.lexer.source()[span.clone()].to_owned();
+
+        CommandParseError::new(ParseErrorKind::UnexpectedToken(token), span)
+    }
+
+    /// Points just past the end of the source.
+    fn eof(&self) -> CommandParseError {
+        let end = self.lexer.source().len();
+
+        CommandParseError::new(ParseErrorKind::UnexpectedEof, end..end)
+    }
+
+    /// Points at whatever the lexer choked on. Anything left unterminated
+    /// runs to the end of the source.
+    fn lexer_error(lexer: &Lexer<'a, Token<'a>>, err: LexerError) -> CommandParseError {
+        let span = match err {
+            LexerError::UnterminatedString | LexerError::UnterminatedSubstitution => {
+                lexer.span().start..lexer.source().len()
+            }
+            LexerError::Unescape(ref err) => {
+                let span = err.span();
+                lexer.span().start + span.start..lexer.span().start + span.end
+            }
+            _ => lexer.span(),
+        };
+
+        CommandParseError::new(err, span)
+    }
+}
+
+/// Whether `name` can be used as the name of a function, which is looser
+/// than what variables allow.
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{cmd::tests::stdout, context::ShellContext};
+
+    /// Writes out how `source` was grouped, with every operator and group
+    /// bracketed.
+    fn shape(source: &str) -> String {
+        fn shape(plan: &ExecutionPlan) -> String {
+            match plan {
+                ExecutionPlan::Execute(cmd) => cmd.words.iter().map(Word::literal).join(" "),
+                ExecutionPlan::Pipe(left, right) => format!("({} | {})", shape(left), shape(right)),
+                ExecutionPlan::And(left, right) => format!("({} && {})", shape(left), shape(right)),
+                ExecutionPlan::Or(left, right) => format!("({} || {})", shape(left), shape(right)),
+                ExecutionPlan::Sequence(plans) => {
+                    format!("[{}]", plans.iter().map(shape).join("; "))
+                }
+                ExecutionPlan::Background(command) => format!("{} &", shape(&command.plan)),
+                ExecutionPlan::BraceGroup(group) => format!("{{ {} }}", shape(&group.body)),
+                ExecutionPlan::Subshell(group) => format!("( {} )", shape(&group.body)),
+                ExecutionPlan::Case(clause) => format!(
+                    "case {} in {}esac",
+                    clause.word.literal(),
+                    clause
+                        .items
+                        .iter()
+                        .map(|item| format!(
+                            "{}) {} {:?} ",
+                            item.patterns.iter().map(Word::literal).join("|"),
+                            shape(&item.body),
+                            item.terminator
+                        ))
+                        .join("")
+                ),
+                plan => format!("{:?}", plan),
+            }
+        }
+
+        shape(&Parser::new(source).parse().unwrap())
+    }
+
+    #[test]
+    fn pipes_bind_tighter_than_and_or() {
+        assert_eq!(shape("a | b && c || d"), "(((a | b) && c) || d)");
+        assert_eq!(shape("a && b | c"), "(a && (b | c))");
+    }
+
+    #[test]
+    fn and_or_associate_to_the_left() {
+        assert_eq!(shape("a || b && c"), "((a || b) && c)");
+        assert_eq!(shape("a && b && c"), "((a && b) && c)");
+    }
+
+    #[test]
+    fn separators_bind_loosest() {
+        assert_eq!(shape("a && b; c || d"), "[(a && b); (c || d)]");
+        assert_eq!(shape("a && b & c"), "[(a && b) &; c]");
+        assert_eq!(shape("a | b &"), "(a | b) &");
+        assert_eq!(shape("a\nb\n\nc\n"), "[a; b; c]");
+    }
+
+    #[test]
+    fn groups_override_precedence() {
+        assert_eq!(shape("a && { b || c; }"), "(a && { (b || c) })");
+        assert_eq!(shape("(a; b) | c"), "(( [a; b] ) | c)");
+    }
+
+    #[test]
+    fn operators_can_be_followed_by_newlines() {
+        assert_eq!(shape("a &&\nb ||\n\nc |\nd"), "((a && b) || (c | d))");
+    }
+
+    #[test]
+    fn misplaced_operators_are_rejected() {
+        for source in ["&& a", "a | | b", "a ;; b", "a && ; b", "a )"] {
+            let err = Parser::new(source).parse().unwrap_err();
+            assert!(
+                matches!(err.kind, ParseErrorKind::UnexpectedToken(_)),
+                "{:?}: {:?}",
+                source,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn case_items_end_with_their_terminator() {
+        assert_eq!(
+            shape("case $x in a|b) c;; d) e;& f) g;;&\n*) h\nesac"),
+            "case $x in a|b) c Break d) e FallThrough f) g Continue *) h Break esac"
+        );
+        assert_eq!(shape("case x in (a) b;; esac"), "case x in a) b Break esac");
+        assert_eq!(
+            shape("case x in\na)\nb\n;;\nesac; c"),
+            "[case x in a) b Break esac; c]"
+        );
+    }
+
+    #[test]
+    fn case_patterns_keep_their_quotes() {
+        let plan = Parser::new(r#"case x in "*"|\?|'a'*) ;; esac"#)
+            .parse()
+            .unwrap();
+        let ExecutionPlan::Case(clause) = plan else {
+            panic!("{:?} isn't a case", plan);
+        };
+
+        let sources = clause.items[0]
+            .patterns
+            .iter()
+            .map(Word::source)
+            .collect::<Vec<_>>();
+        assert_eq!(sources, [r#""*""#, r"\?", "'a'*"]);
+    }
+
+    #[test]
+    fn esac_is_only_special_where_a_command_could_end_the_case() {
+        assert_eq!(shape("echo esac"), "echo esac");
+        assert_eq!(
+            shape("case x in a) echo esac;; esac"),
+            "case x in a) echo esac Break esac"
+        );
+        assert_eq!(
+            shape("case esac in (esac) a;; esac"),
+            "case esac in esac) a Break esac"
+        );
+        // without the `(` it would be the end of the case, as in bash
+        assert!(Parser::new("case esac in esac) a;; esac").parse().is_err());
+    }
+
+    /// The heredocs of the command `source` in the order they were written,
+    /// along with whether each one is expanded.
+    fn heredocs(source: &str) -> Vec<(String, bool)> {
+        heredocs_of(Parser::new(source).parse().unwrap())
+    }
+
+    fn heredocs_of(mut plan: ExecutionPlan) -> Vec<(String, bool)> {
+        let mut heredocs = Vec::new();
+
+        while let ExecutionPlan::RedirectPipe(inner, redirection) = plan {
+            if let PipeType::HereDoc(heredoc) = redirection.from {
+                heredocs.push((heredoc.body, heredoc.expand));
+            }
+            plan = *inner;
+        }
+
+        heredocs
+    }
+
+    #[test]
+    fn heredoc_bodies_follow_the_line() {
+        assert_eq!(
+            heredocs("cat <<EOF\na\n\tb $x\nEOF"),
+            [(String::from("a\n\tb $x\n"), true)]
+        );
+        assert_eq!(heredocs("cat <<EOF\nEOF"), [(String::new(), true)]);
+        assert_eq!(
+            heredocs("cat <<-EOF\n\ta\n\t\tb\n  c\n\tEOF"),
+            [(String::from("a\nb\n  c\n"), true)]
+        );
+    }
+
+    #[test]
+    fn quoted_heredoc_delimiters_turn_off_expansion() {
+        for source in [
+            "cat <<'EOF'\n$x\nEOF",
+            "cat <<\"EOF\"\n$x\nEOF",
+            "cat <<\\EOF\n$x\nEOF",
+            "cat <<E'O'F\n$x\nEOF",
+        ] {
+            assert_eq!(
+                heredocs(source),
+                [(String::from("$x\n"), false)],
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn heredocs_on_one_line_are_read_in_order() {
+        assert_eq!(
+            heredocs("cat <<A <<'B'\n1\nA\n2\nB"),
+            [(String::from("1\n"), true), (String::from("2\n"), false)]
+        );
+
+        let plan = Parser::new("cat <<A; cat <<B\n1\nA\n2\nB\necho done")
+            .parse()
+            .unwrap();
+        let ExecutionPlan::Sequence(plans) = plan else {
+            panic!("{:?} isn't a sequence", plan);
+        };
+        let bodies = plans.into_iter().map(heredocs_of).collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [
+                vec![(String::from("1\n"), true)],
+                vec![(String::from("2\n"), true)],
+                vec![]
+            ]
+        );
+        assert!(Parser::new("cat <<A <<B\n1\nA\n2").parse().is_err());
+    }
+
+    #[test]
+    fn here_strings_are_words() {
+        let plan = Parser::new("cat <<< \"$x y\"").parse().unwrap();
+        let ExecutionPlan::RedirectPipe(_, redirection) = plan else {
+            panic!("{:?} isn't redirected", plan);
+        };
+
+        assert!(
+            matches!(&redirection.from, PipeType::HereString(word) if word.source() == "\"$x y\""),
+            "{:?}",
+            redirection
+        );
+        assert!(matches!(redirection.to, PipeType::Stdin));
+    }
+
+    #[test]
+    fn keywords_are_only_reserved_in_command_position() {
+        assert_eq!(
+            shape("echo done in for if then esac fi {"),
+            "echo done in for if then esac fi {"
+        );
+        assert_eq!(shape("{ echo }; }"), "{ echo } }");
+        assert!(Parser::new("for in in in; do a; done").parse().is_ok());
+        assert!(Parser::new("for a-b in x; do a; done").parse().is_err());
+    }
+
+    #[tokio::test]
+    async fn words_take_anything_but_metacharacters() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(
+            stdout(&ctx, "echo --format=%h a:b café.txt x@y +1 ,~ 100% a#b").await,
+            "--format=%h a:b café.txt x@y +1 ,~ 100% a#b\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "echo done; if echo if; then echo then; fi # done").await,
+            "done\nif\nthen\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "for in in in out; do echo $in; done").await,
+            "in\nout\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Finds where constructs that can nest end, which the lexer's regular
+//! expressions cannot do on their own. Each function takes everything after
+//! the opening delimiter and returns the offset of the closing one, or `None`
+//! if it is never closed.
+
+/// Finds the `)` closing a `$(`.
+pub fn substitution_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'\'') => i += 2 + ansi_c_quote_end(&s[i + 2..])?,
+            b'\'' => i += 1 + s[i + 1..].find('\'')?,
+            b'"' => i += 1 + double_quote_end(&s[i + 1..])?,
+            b'`' => i += 1 + backtick_end(&s[i + 1..])?,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i += 2 + parameter_end(&s[i + 2..])?,
+            b'(' => depth += 1,
+            b')' if depth == 0 => return Some(i),
+            b')' => depth -= 1,
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Finds the `))` closing a `$((`, or `None` if the parentheses inside
+/// don't line up with it, in which case it is really a command substitution
+/// starting with a subshell.
+pub fn arithmetic_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+
+    for (i, c) in bytes.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' if depth == 0 => return (bytes.get(i + 1) == Some(&b')')).then_some(i),
+            b')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Finds the `}` closing a `${`. Double quotes and substitutions inside of
+/// it are skipped over, so that `${x:-"}"}` ends at the last brace. Single
+/// quotes are left alone, since inside double quotes `"${x:-it's}"` is an
+/// apostrophe.
+pub fn parameter_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'(') => i += 2 + substitution_end(&s[i + 2..])?,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i += 2 + parameter_end(&s[i + 2..])?,
+            b'"' => i += 1 + double_quote_end(&s[i + 1..])?,
+            b'`' => i += 1 + backtick_end(&s[i + 1..])?,
+            b'}' => return Some(i),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Finds the closing backtick of a `` `...` `` substitution.
+pub fn backtick_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'`' => return Some(i),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Finds the `"` closing a double quoted string, skipping over any
+/// substitutions inside of it, along with whatever they quote themselves.
+pub fn double_quote_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'(') => i += 2 + substitution_end(&s[i + 2..])?,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i += 2 + parameter_end(&s[i + 2..])?,
+            b'`' => i += 1 + backtick_end(&s[i + 1..])?,
+            b'"' => return Some(i),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Finds the `'` closing a `$'...'` string, in which `\'` is an escaped
+/// quote rather than the end of it.
+pub fn ansi_c_quote_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' => return Some(i),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Finds the length of the unquoted part of the word at the start of `s`,
+/// which ends at a metacharacter or a quote, including the `$'` that starts
+/// an ANSI-C quoted string.
+pub fn unquoted_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', Some(_)) => i += 1 + s[i + 1..].chars().next()?.len_utf8(),
+            (b'$', Some(b'\'')) => break,
+            (b'$', Some(b'(')) => i += 2 + substitution_end(&s[i + 2..])? + 1,
+            (b'$', Some(b'{')) => i += 2 + parameter_end(&s[i + 2..])? + 1,
+            (b'`', _) => i += 1 + backtick_end(&s[i + 1..])? + 1,
+            (c, _) if is_metacharacter(c) => break,
+            _ => i += 1,
+        }
+    }
+
+    Some(i)
+}
+
+/// Whether `c` ends a word, either by separating it from the next one or
+/// by starting an operator or a quoted string.
+fn is_metacharacter(c: u8) -> bool {
+    matches!(
+        c,
+        b' ' | b'\t'
+            | b'\x0c'
+            | b'\r'
+            | b'\n'
+            | b'|'
+            | b'&'
+            | b';'
+            | b'('
+            | b')'
+            | b'<'
+            | b'>'
+            | b'"'
+            | b'\''
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_constructs_are_skipped() {
+        assert_eq!(substitution_end("echo (a) \")\" ')' `)` $(b))"), Some(25));
+        assert_eq!(
+            double_quote_end(r#"a\"b $(echo ")") ${x:-"}"}"c"#),
+            Some(26)
+        );
+        assert_eq!(parameter_end(r#"x:-"}" ${y} $(echo }) \}}"#), Some(24));
+        assert_eq!(backtick_end(r"echo \` x` y"), Some(9));
+        assert_eq!(ansi_c_quote_end(r"a\'b' c"), Some(4));
+    }
+
+    #[test]
+    fn arithmetic_needs_balanced_parentheses() {
+        assert_eq!(arithmetic_end("1 + (2 * 3)))"), Some(11));
+        assert_eq!(arithmetic_end("a) | (b)"), None);
+    }
+
+    #[test]
+    fn unclosed_constructs() {
+        assert_eq!(substitution_end("echo \")\""), None);
+        assert_eq!(double_quote_end("$(\")"), None);
+        assert_eq!(parameter_end("x:-\"}"), None);
+    }
+
+    #[test]
+    fn unquoted_words_end_at_metacharacters() {
+        assert_eq!(unquoted_len("abc def"), Some(3));
+        assert_eq!(unquoted_len(r"a\ b;c"), Some(4));
+        assert_eq!(unquoted_len("a$(b c)d|e"), Some(8));
+        assert_eq!(unquoted_len("a${b:-c d}e'f'"), Some(11));
+        assert_eq!(unquoted_len("a$'b'"), Some(1));
+        assert_eq!(unquoted_len("a$(b"), None);
+    }
+}
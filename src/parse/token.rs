@@ -1,21 +1,26 @@
-use logos::Logos;
+use logos::{Lexer, Logos};
 use thiserror::Error;
 
-use super::unescape::{self, unescape};
+use super::{unescape, word};
 
 #[derive(Debug, Clone, PartialEq, Default, Error)]
 pub enum LexerError {
     #[default]
-    #[error("unknown token")]
+    #[error("unexpected character")]
     UnknownToken,
     #[error("unterminated string")]
     UnterminatedString,
-    #[error("unescape error: {0}")]
+    #[error("unterminated substitution")]
+    UnterminatedSubstitution,
+    #[error("unexpected end of input after `\\`")]
+    TrailingBackslash,
+    #[error("{0}")]
     Unescape(#[from] unescape::UnescapeError),
 }
 
-#[derive(Debug, PartialEq, Logos)]
-#[logos(skip r"[ \t\n\f]+", error = LexerError)]
+#[derive(Debug, Clone, PartialEq, Logos)]
+// a `\` at the end of a line joins it onto the next one
+#[logos(skip r"([ \t\f]|\\\n)+", error = LexerError)]
 pub enum Token<'a> {
     #[token("&&")]
     And,
@@ -23,26 +28,54 @@ pub enum Token<'a> {
     Or,
     #[token(";")]
     Semi,
+    #[token(";;")]
+    DoubleSemi,
+    #[token(";&")]
+    SemiAnd,
+    #[token(";;&")]
+    DoubleSemiAnd,
+    #[token("\n")]
+    Newline,
     #[token("|")]
     Pipe,
-    #[token(">>")]
-    Append,
-    #[token(">")]
-    Write,
+    #[token("&")]
+    Ampersand,
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[regex(r"[0-9]*>>", |lex| descriptor(lex, 1))]
+    Append(u32),
+    #[regex(r"[0-9]*>", |lex| descriptor(lex, 1))]
+    Write(u32),
+    #[regex(r"[0-9]*>&[0-9]+", duplicate)]
+    Duplicate((u32, u32)),
+    #[token("&>>")]
+    AppendAll,
+    #[token("&>")]
+    WriteAll,
+    #[token("<<<")]
+    HereString,
+    #[token("<<-")]
+    HereDocStrip,
     #[token("<<")]
     HereDoc,
-    #[token("<")]
-    Read,
+    #[regex(r"[0-9]*<", |lex| descriptor(lex, 0))]
+    Read(u32),
     #[token("if")]
     If,
     #[token("then")]
     Then,
+    #[token("elif")]
+    Elif,
     #[token("else")]
     Else,
     #[token("fi")]
     Fi,
     #[token("while")]
     While,
+    #[token("until")]
+    Until,
     #[token("do")]
     Do,
     #[token("done")]
@@ -62,14 +95,45 @@ pub enum Token<'a> {
     #[token("continue")]
     Continue,
 
-    #[regex(r"[a-zA-Z0-9_\-\./]*", priority = 2)]
-    Identifier(&'a str),
-    #[regex(r#""([^"\\]|\\.)*""#, |lex| unescape(lex.slice()))]
-    DoubleQuotedString(String),
-    #[regex(r"'[^']*'", |lex| unescape(lex.slice()))]
-    SingleQuotedString(String),
+    #[regex(r#"[^ \t\f\r\n|&;()<>#]+"#, word, priority = 2)]
+    Word(&'a str),
     #[regex(r"#.*")]
     Comment(&'a str),
     #[regex(r"\d+", |lex| lex.slice().parse().ok())]
     Number(i64),
 }
+
+/// The regex only finds the start of a word, this finds where it really
+/// ends, since quotes, parameters and command substitutions can nest. A word
+/// is made of anything but metacharacters, and can contain a `#` as long as
+/// it doesn't start with one.
+fn word<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<&'a str, LexerError> {
+    let (_, len) = word::segments(&lex.source()[lex.span().start..])?;
+
+    lex.bump(len - lex.slice().len());
+
+    Ok(lex.slice())
+}
+
+/// Parses the file descriptor a redirection operator starts with, if any.
+fn descriptor<'a>(lex: &mut Lexer<'a, Token<'a>>, default: u32) -> Option<u32> {
+    let fd = lex.slice().trim_end_matches(['>', '<']);
+
+    if fd.is_empty() {
+        Some(default)
+    } else {
+        fd.parse().ok()
+    }
+}
+
+fn duplicate<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Option<(u32, u32)> {
+    let (from, to) = lex.slice().split_once(">&")?;
+
+    let from = if from.is_empty() {
+        1
+    } else {
+        from.parse().ok()?
+    };
+
+    Some((from, to.parse().ok()?))
+}
@@ -1,12 +1,16 @@
-// ripped from https://github.com/euank/snailquote/blob/7a4aee264595a742a90759b9a22d038d721b54b4/src/lib.rs
+// originally ripped from https://github.com/euank/snailquote/blob/7a4aee264595a742a90759b9a22d038d721b54b4/src/lib.rs
 
-use std::num::ParseIntError;
+use std::{iter::Peekable, num::ParseIntError, str::CharIndices};
 
 use thiserror::Error;
 
+use super::Span;
+
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum ParseUnicodeError {
-    #[error("expected '{{' character in unicode escape")]
+    #[error("expected hex digits in unicode escape")]
+    DigitsNotFound,
+    #[error("expected '}}' character in unicode escape")]
     BraceNotFound,
     #[error("could not parse {string} as u32 hex: {source}")]
     ParseHexFailed {
@@ -18,122 +22,277 @@ pub enum ParseUnicodeError {
     ParseUnicodeFailed { value: u32 },
 }
 
+/// An escape sequence that could not be decoded. `index` is the byte offset
+/// of its backslash in the string being unescaped.
 #[derive(Debug, Clone, PartialEq, Error)]
 pub enum UnescapeError {
-    #[error("invalid escape {escape} at {index} in {string}")]
-    InvalidEscape {
-        escape: String,
-        index: usize,
-        string: String,
-    },
-    #[error("\\u could not be parsed at {index} in {string}: {source}")]
+    #[error("invalid escape `{escape}'")]
+    InvalidEscape { escape: String, index: usize },
+    #[error("invalid escape `{escape}': {source}")]
     InvalidUnicode {
         #[source]
         source: ParseUnicodeError,
+        escape: String,
         index: usize,
-        string: String,
     },
 }
 
-pub fn unescape(s: &str) -> Result<String, UnescapeError> {
-    let mut in_single_quote = false;
-    let mut in_double_quote = false;
+impl UnescapeError {
+    /// Where the escape sequence sits in the string that was unescaped.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::InvalidEscape { escape, index } | Self::InvalidUnicode { escape, index, .. } => {
+                *index..*index + escape.len()
+            }
+        }
+    }
+
+    /// Moves the error `offset` bytes further along, for when the string
+    /// that was unescaped is part of a larger one.
+    pub fn offset(mut self, offset: usize) -> Self {
+        match &mut self {
+            Self::InvalidEscape { index, .. } | Self::InvalidUnicode { index, .. } => {
+                *index += offset
+            }
+        }
+
+        self
+    }
+}
+
+/// A decoded escape sequence. `\xHH` and octal escapes stand for a single
+/// byte rather than a character, so that `$'\xc3\xa9'` spells out `é`.
+enum Escape {
+    Char(char),
+    Byte(u8),
+}
 
-    let mut chars = s.chars().enumerate();
+/// Decodes every escape sequence in `s`, which is the body of a `$'...'`
+/// string.
+pub fn unescape(s: &str) -> Result<String, UnescapeError> {
+    let mut chars = s.char_indices().peekable();
 
-    let mut res = String::with_capacity(s.len());
+    let mut res = Vec::with_capacity(s.len());
 
     while let Some((idx, c)) = chars.next() {
-        // when in a single quote, no escapes are possible
-        if in_single_quote {
-            if c == '\'' {
-                in_single_quote = false;
-                continue;
+        match c {
+            '\\' => push_escape(&mut res, escape(s, idx, &mut chars)?),
+            c => res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+
+    Ok(decode(&res))
+}
+
+fn push_escape(res: &mut Vec<u8>, escape: Escape) {
+    match escape {
+        Escape::Char(c) => res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        Escape::Byte(byte) => res.push(byte),
+    }
+}
+
+/// Decodes `bytes` as UTF-8. Bytes which aren't part of a valid character
+/// are kept as the character with the same value, rather than being lost.
+fn decode(mut bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len());
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                res.push_str(valid);
+                break res;
             }
-        } else if in_double_quote {
-            if c == '"' {
-                in_double_quote = false;
-                continue;
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                // already checked by `from_utf8`
+                res.push_str(std::str::from_utf8(valid).unwrap());
+
+                let invalid = err.error_len().unwrap_or(rest.len());
+                res.extend(rest[..invalid].iter().map(|&byte| char::from(byte)));
+                bytes = &rest[invalid..];
             }
+        }
+    }
+}
+
+fn escape(s: &str, idx: usize, chars: &mut Peekable<CharIndices>) -> Result<Escape, UnescapeError> {
+    let invalid = |chars: &mut Peekable<CharIndices>| UnescapeError::InvalidEscape {
+        escape: escape_text(s, idx, chars),
+        index: idx,
+    };
+
+    let unicode = |source, chars: &mut Peekable<CharIndices>| UnescapeError::InvalidUnicode {
+        source,
+        escape: escape_text(s, idx, chars),
+        index: idx,
+    };
+
+    let c = match chars.next() {
+        Some((_, c)) => c,
+        None => return Err(invalid(chars)),
+    };
 
-            if c == '\\' {
-                match chars.next() {
-                    None => {
-                        return Err(UnescapeError::InvalidEscape {
-                            escape: format!("{}", c),
-                            index: idx,
-                            string: String::from(s),
-                        });
-                    }
-                    Some((idx, c2)) => {
-                        res.push(match c2 {
-                            'a' => '\u{07}',
-                            'b' => '\u{08}',
-                            'v' => '\u{0B}',
-                            'f' => '\u{0C}',
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            'e' | 'E' => '\u{1B}',
-                            '\\' => '\\',
-                            '\'' => '\'',
-                            '"' => '"',
-                            '$' => '$',
-                            '`' => '`',
-                            ' ' => ' ',
-                            'u' => parse_unicode(&mut chars).map_err(|x| {
-                                UnescapeError::InvalidUnicode {
-                                    source: x,
-                                    index: idx,
-                                    string: String::from(s),
-                                }
-                            })?,
-                            _ => {
-                                return Err(UnescapeError::InvalidEscape {
-                                    escape: format!("{}{}", c, c2),
-                                    index: idx,
-                                    string: String::from(s),
-                                });
-                            }
-                        });
-                        continue;
-                    }
-                };
+    Ok(Escape::Char(match c {
+        'a' => '\u{07}',
+        'b' => '\u{08}',
+        'v' => '\u{0B}',
+        'f' => '\u{0C}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'e' | 'E' => '\u{1B}',
+        '\\' => '\\',
+        '\'' => '\'',
+        '"' => '"',
+        '?' => '?',
+        '$' => '$',
+        '`' => '`',
+        ' ' => ' ',
+        'x' => match digits(chars, 16, 2) {
+            Some(byte) => return Ok(Escape::Byte(byte as u8)),
+            None => return Err(invalid(chars)),
+        },
+        '0'..='7' => {
+            let mut value = c as u32 - '0' as u32;
+
+            for _ in 0..2 {
+                match chars.peek().and_then(|&(_, c)| c.to_digit(8)) {
+                    Some(digit) => value = value * 8 + digit,
+                    None => break,
+                }
+                chars.next();
             }
-        } else if c == '\'' {
-            in_single_quote = true;
-            continue;
-        } else if c == '"' {
-            in_double_quote = true;
-            continue;
+
+            // like in C, `\777` doesn't fit in a byte and wraps around
+            return Ok(Escape::Byte(value as u8));
         }
+        'u' | 'U' => {
+            let max = if c == 'u' { 4 } else { 8 };
+            parse_unicode(chars, max).map_err(|source| unicode(source, chars))?
+        }
+        'c' => match chars.next() {
+            Some((_, c)) if c.is_ascii() => (c.to_ascii_uppercase() as u8 ^ 0x40) as char,
+            _ => return Err(invalid(chars)),
+        },
+        _ => return Err(invalid(chars)),
+    }))
+}
 
-        res.push(c);
-    }
+/// The text of the escape sequence starting at `idx`, up to wherever `chars`
+/// has got to.
+fn escape_text(s: &str, idx: usize, chars: &mut Peekable<CharIndices>) -> String {
+    let end = chars.peek().map_or(s.len(), |&(end, _)| end);
 
-    Ok(res)
+    s[idx..end].to_owned()
 }
 
-fn parse_unicode<I>(chars: &mut I) -> Result<char, ParseUnicodeError>
-where
-    I: Iterator<Item = (usize, char)>,
-{
-    match chars.next() {
-        Some((_, '{')) => {}
-        _ => {
-            return Err(ParseUnicodeError::BraceNotFound);
+/// Reads up to `max` digits in `radix`, returning `None` if there weren't
+/// any.
+fn digits(chars: &mut Peekable<CharIndices>, radix: u32, max: usize) -> Option<u32> {
+    let mut value = None;
+
+    for _ in 0..max {
+        match chars.peek().and_then(|&(_, c)| c.to_digit(radix)) {
+            Some(digit) => value = Some(value.unwrap_or(0) * radix + digit),
+            None => break,
         }
+        chars.next();
     }
 
-    let unicode_seq: String = chars
-        .take_while(|&(_, c)| c != '}')
-        .map(|(_, c)| c)
-        .collect();
+    value
+}
+
+/// Parses the rest of a `\uHHHH` or `\UHHHHHHHH` escape, which can have up
+/// to `max` hex digits. `\u{...}` is accepted too.
+fn parse_unicode(chars: &mut Peekable<CharIndices>, max: usize) -> Result<char, ParseUnicodeError> {
+    let value = if chars.next_if(|&(_, c)| c == '{').is_some() {
+        let mut unicode_seq = String::new();
 
-    u32::from_str_radix(&unicode_seq, 16)
-        .map_err(|e| ParseUnicodeError::ParseHexFailed {
+        loop {
+            match chars.next() {
+                Some((_, '}')) => break,
+                Some((_, c)) => unicode_seq.push(c),
+                None => return Err(ParseUnicodeError::BraceNotFound),
+            }
+        }
+
+        u32::from_str_radix(&unicode_seq, 16).map_err(|e| ParseUnicodeError::ParseHexFailed {
             source: e,
             string: unicode_seq,
-        })
-        .and_then(|u| char::from_u32(u).ok_or(ParseUnicodeError::ParseUnicodeFailed { value: u }))
+        })?
+    } else {
+        digits(chars, 16, max).ok_or(ParseUnicodeError::DigitsNotFound)?
+    };
+
+    char::from_u32(value).ok_or(ParseUnicodeError::ParseUnicodeFailed { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_escapes() {
+        assert_eq!(
+            unescape(r#"\a\b\v\f\n\r\t\e\E\\\'\"\?\$\`\ "#).unwrap(),
+            "\u{07}\u{08}\u{0B}\u{0C}\n\r\t\u{1B}\u{1B}\\'\"?$` "
+        );
+        assert_eq!(unescape("plain é").unwrap(), "plain é");
+    }
+
+    #[test]
+    fn numeric_escapes() {
+        assert_eq!(unescape(r"\x41\x4a\x7").unwrap(), "AJ\u{07}");
+        assert_eq!(unescape(r"\101\0\1010").unwrap(), "A\0A0");
+        assert_eq!(unescape(r"é\U0001F600\u{1F600}").unwrap(), "é😀😀");
+        assert_eq!(unescape(r"\ca\c[").unwrap(), "\u{01}\u{1B}");
+    }
+
+    #[test]
+    fn byte_escapes_are_decoded_as_utf8() {
+        assert_eq!(unescape(r"\xc3\xa9").unwrap(), "é");
+        assert_eq!(unescape(r"\303\251").unwrap(), "é");
+        assert_eq!(unescape(r"\xe2\x82\xac!").unwrap(), "€!");
+        // bytes that aren't valid UTF-8 keep their value
+        assert_eq!(unescape(r"\xff\xc3").unwrap(), "\u{ff}\u{c3}");
+        assert_eq!(unescape(r"\xc3a").unwrap(), "\u{c3}a");
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        let error = |s| unescape(s).unwrap_err();
+
+        assert_eq!(
+            error(r"ab\q"),
+            UnescapeError::InvalidEscape {
+                escape: String::from(r"\q"),
+                index: 2,
+            }
+        );
+        assert_eq!(error(r"\xg").span(), 0..2);
+        assert_eq!(error(r"a\").span(), 1..2);
+        assert_eq!(error(r"\c").span(), 0..2);
+        assert!(matches!(
+            error(r"\u{110000}"),
+            UnescapeError::InvalidUnicode {
+                source: ParseUnicodeError::ParseUnicodeFailed { value: 0x110000 },
+                ..
+            }
+        ));
+        assert!(matches!(
+            error(r"\u{41"),
+            UnescapeError::InvalidUnicode {
+                source: ParseUnicodeError::BraceNotFound,
+                ..
+            }
+        ));
+        assert!(matches!(
+            error(r"\uzz"),
+            UnescapeError::InvalidUnicode {
+                source: ParseUnicodeError::DigitsNotFound,
+                ..
+            }
+        ));
+        assert_eq!(error(r"x\q").offset(3).span(), 4..6);
+    }
 }
@@ -0,0 +1,343 @@
+use super::{scan, token::LexerError, unescape::unescape, Span};
+
+/// A single word of a command, made of the differently quoted segments
+/// written next to each other, so `foo"bar"'baz'` is one word of three
+/// segments.
+///
+/// Each segment's value is kept as written (minus the surrounding quotes) so
+/// that expansions can tell escaped characters apart from special ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub segments: Vec<Segment>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub value: String,
+    pub quoting: Quoting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// Plain text, where `\` escapes the character after it.
+    Unquoted,
+    /// `"..."`, where parameters are still expanded but not split, and `\`
+    /// only escapes the characters that would otherwise be special.
+    Double,
+    /// `'...'`, where everything is taken literally.
+    Single,
+    /// `$'...'`, where `\` starts an escape sequence like in C.
+    AnsiC,
+}
+
+impl Word {
+    /// Creates a word made of a single segment.
+    pub fn new(value: impl Into<String>, quoting: Quoting, span: Span) -> Self {
+        Self {
+            segments: vec![Segment {
+                value: value.into(),
+                quoting,
+            }],
+            span,
+        }
+    }
+
+    /// Splits the word `source` up into its segments.
+    pub fn parse(source: &str, span: Span) -> Result<Self, LexerError> {
+        let (segments, _) = segments(source)?;
+
+        Ok(Self { segments, span })
+    }
+
+    /// Splits a `NAME=value` word into its name and value, if it is one.
+    pub fn assignment(&self) -> Option<(&str, Word)> {
+        let first = self.segments.first()?;
+
+        if first.quoting != Quoting::Unquoted {
+            return None;
+        }
+
+        let (name, value) = first.value.split_once('=')?;
+
+        if !is_name(name) {
+            return None;
+        }
+
+        let mut segments = Vec::new();
+
+        if !value.is_empty() {
+            segments.push(Segment {
+                value: value.to_owned(),
+                quoting: Quoting::Unquoted,
+            });
+        }
+
+        segments.extend(self.segments[1..].iter().cloned());
+
+        let start = self.span.start + name.len() + 1;
+
+        Some((
+            name,
+            Word {
+                segments,
+                span: start..self.span.end,
+            },
+        ))
+    }
+
+    /// The word with its quotes removed, but nothing expanded. This is what
+    /// a heredoc delimiter is compared against.
+    pub fn literal(&self) -> String {
+        let mut literal = String::new();
+
+        for segment in &self.segments {
+            match segment.quoting {
+                Quoting::Unquoted => {
+                    let mut chars = segment.value.chars();
+
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => literal.extend(chars.next().filter(|&c| c != '\n')),
+                            c => literal.push(c),
+                        }
+                    }
+                }
+                Quoting::Double => {
+                    let mut chars = segment.value.chars().peekable();
+
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => {
+                                match chars.next_if(|&c| escapes_in_double_quotes(c) || c == '\n') {
+                                    Some('\n') => {}
+                                    Some(c) => literal.push(c),
+                                    None => literal.push('\\'),
+                                }
+                            }
+                            c => literal.push(c),
+                        }
+                    }
+                }
+                Quoting::Single => literal.push_str(&segment.value),
+                // the escapes were checked when the word was parsed
+                Quoting::AnsiC => literal.push_str(&unescape(&segment.value).unwrap_or_default()),
+            }
+        }
+
+        literal
+    }
+
+    /// Writes the word back out the way it was written, quotes and all.
+    pub fn source(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment.quoting {
+                Quoting::Unquoted => segment.value.clone(),
+                Quoting::Double => format!("\"{}\"", segment.value),
+                Quoting::Single => format!("'{}'", segment.value),
+                Quoting::AnsiC => format!("$'{}'", segment.value),
+            })
+            .collect()
+    }
+}
+
+/// Splits the word at the start of `s` into its segments, returning them
+/// along with how many bytes of `s` the word took up.
+///
+/// The escape sequences in `$'...'` are only decoded during expansion, but
+/// they are checked here so that a bad one is reported as soon as the command
+/// is parsed.
+pub fn segments(s: &str) -> Result<(Vec<Segment>, usize), LexerError> {
+    let mut segments = Vec::new();
+    let mut i = 0;
+
+    loop {
+        let bytes = s.as_bytes();
+
+        let (value, quoting, len) = match bytes.get(i) {
+            Some(b'$') if bytes.get(i + 1) == Some(&b'\'') => {
+                let end =
+                    scan::ansi_c_quote_end(&s[i + 2..]).ok_or(LexerError::UnterminatedString)?;
+                let value = &s[i + 2..i + 2 + end];
+                unescape(value).map_err(|err| err.offset(i + 2))?;
+                (value, Quoting::AnsiC, end + 3)
+            }
+            Some(b'\'') => {
+                let end = s[i + 1..]
+                    .find('\'')
+                    .ok_or(LexerError::UnterminatedString)?;
+                (&s[i + 1..i + 1 + end], Quoting::Single, end + 2)
+            }
+            Some(b'"') => {
+                let end =
+                    scan::double_quote_end(&s[i + 1..]).ok_or(LexerError::UnterminatedString)?;
+                (&s[i + 1..i + 1 + end], Quoting::Double, end + 2)
+            }
+            Some(_) => match scan::unquoted_len(&s[i..]) {
+                Some(0) => break,
+                // the line the word is on is being continued onto the next
+                Some(len) if i + len == s.len() && ends_with_backslash(&s[i..]) => {
+                    return Err(LexerError::TrailingBackslash)
+                }
+                Some(len) => (&s[i..i + len], Quoting::Unquoted, len),
+                None => return Err(LexerError::UnterminatedSubstitution),
+            },
+            None => break,
+        };
+
+        segments.push(Segment {
+            value: value.to_owned(),
+            quoting,
+        });
+        i += len;
+    }
+
+    Ok((segments, i))
+}
+
+/// Whether `s` ends with a `\` that isn't itself escaped.
+fn ends_with_backslash(s: &str) -> bool {
+    (s.len() - s.trim_end_matches('\\').len()) % 2 == 1
+}
+
+/// Whether a `\` inside of double quotes escapes `c`. Before anything else it
+/// is just a backslash, so `"\."` is two characters.
+pub fn escapes_in_double_quotes(c: char) -> bool {
+    matches!(c, '$' | '`' | '"' | '\\')
+}
+
+/// Whether `name` can be used as the name of a variable.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(value: &str, quoting: Quoting) -> Segment {
+        Segment {
+            value: value.to_owned(),
+            quoting,
+        }
+    }
+
+    fn word(source: &str) -> Word {
+        Word::parse(source, 0..source.len()).unwrap()
+    }
+
+    #[test]
+    fn quoted_parts_make_up_one_word() {
+        assert_eq!(
+            segments(r#"a"b c"'d e'$'\n'f g"#).unwrap(),
+            (
+                vec![
+                    segment("a", Quoting::Unquoted),
+                    segment("b c", Quoting::Double),
+                    segment("d e", Quoting::Single),
+                    segment(r"\n", Quoting::AnsiC),
+                    segment("f", Quoting::Unquoted),
+                ],
+                17
+            )
+        );
+        assert_eq!(segments(" a").unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn substitutions_stay_in_their_segment() {
+        assert_eq!(
+            segments(r#"$(echo "a b") ${x:-"c d"}"#).unwrap(),
+            (vec![segment(r#"$(echo "a b")"#, Quoting::Unquoted)], 13)
+        );
+        assert_eq!(
+            segments(r#""$(echo ")")""#).unwrap(),
+            (vec![segment(r#"$(echo ")")"#, Quoting::Double)], 13)
+        );
+        assert_eq!(
+            segments(r#"${x:-"c d"};"#).unwrap(),
+            (vec![segment(r#"${x:-"c d"}"#, Quoting::Unquoted)], 11)
+        );
+    }
+
+    #[test]
+    fn escapes_keep_a_character_in_the_word() {
+        assert_eq!(
+            segments(r"a\ b\'c\\").unwrap(),
+            (vec![segment(r"a\ b\'c\\", Quoting::Unquoted)], 9)
+        );
+        assert_eq!(segments(r"a\ b\'c\\\"), Err(LexerError::TrailingBackslash));
+        assert_eq!(
+            segments(r#""a\"b"c"#).unwrap(),
+            (
+                vec![
+                    segment(r#"a\"b"#, Quoting::Double),
+                    segment("c", Quoting::Unquoted)
+                ],
+                7
+            )
+        );
+        assert_eq!(
+            segments(r"$'a\'b'").unwrap(),
+            (vec![segment(r"a\'b", Quoting::AnsiC)], 7)
+        );
+    }
+
+    #[test]
+    fn unclosed_quotes() {
+        for s in ["\"a", "'a", "$'a\\'", "a\"b", "$(a", "\"$(a\""] {
+            assert!(segments(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn bad_escapes_are_found_when_parsing() {
+        assert_eq!(
+            segments(r"ab$'\q'").map_err(|err| match err {
+                LexerError::Unescape(err) => err.span(),
+                err => panic!("{:?}", err),
+            }),
+            Err(4..6)
+        );
+    }
+
+    #[test]
+    fn literal_removes_quotes_only() {
+        assert_eq!(
+            word(r#"a\ b"c\"\$x"'\n'$'\x41'"#).literal(),
+            r#"a bc"$x\nA"#
+        );
+        assert_eq!(word("\"$x\"${y}").literal(), "$x${y}");
+    }
+
+    #[test]
+    fn source_is_written_back_out() {
+        for source in [r#"a"b c"'d'$'\n'"#, r"a\ b", r#""$(echo "x")""#] {
+            assert_eq!(word(source).source(), source);
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        let assignment = word("a_1=b\"c\"");
+        let (name, value) = assignment.assignment().unwrap();
+        assert_eq!(name, "a_1");
+        assert_eq!(
+            value.segments,
+            [
+                segment("b", Quoting::Unquoted),
+                segment("c", Quoting::Double)
+            ]
+        );
+        assert_eq!(value.span, 4..8);
+
+        assert_eq!(word("a=").assignment().unwrap().1.segments, []);
+        assert_eq!(word("1a=b").assignment(), None);
+        assert_eq!(word("\"a\"=b").assignment(), None);
+        assert_eq!(word("=b").assignment(), None);
+    }
+}
@@ -1,4 +1,9 @@
-use std::{io, process::ExitStatus};
+use std::{
+    io,
+    process::ExitStatus,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
 
 use nix::{
     sys::signal::{kill, Signal},
@@ -14,7 +19,11 @@ pub enum VashChild {
     Process(Child),
     Delegate(ChildDelegate),
     PreExecuted(BuiltinExitStatus),
-    Thread(JoinHandle<VashExitStatus>),
+    /// An adhoc process, along with whatever it is running at the moment.
+    Thread(JoinHandle<VashExitStatus>, Running),
+    /// A thread which has already been waited on. Join handles cannot be
+    /// polled again once they resolve, so the status is kept here instead.
+    Exited(VashExitStatus),
 }
 
 impl From<Child> for VashChild {
@@ -24,14 +33,28 @@ impl From<Child> for VashChild {
 }
 
 impl VashChild {
+    /// The process id of the child, if it is a real process that is still
+    /// running.
+    pub fn id(&self) -> Option<u32> {
+        match self {
+            Self::Process(process) => process.id(),
+            _ => None,
+        }
+    }
+
     pub async fn wait(&mut self) -> io::Result<VashExitStatus> {
         match self {
             Self::Process(process) => process.wait().await.map(Into::into),
             Self::Delegate(delegate) => delegate.wait().await.map(Into::into),
             Self::PreExecuted(status) => Ok(VashExitStatus::from(*status)),
-            Self::Thread(handle) => handle.await.map_err(|_| {
-                io::Error::new(io::ErrorKind::BrokenPipe, "Child exited unexpectedly")
-            }),
+            Self::Thread(handle, _) => {
+                let status = handle.await.map_err(|_| {
+                    io::Error::new(io::ErrorKind::BrokenPipe, "Child exited unexpectedly")
+                })?;
+                *self = Self::Exited(status);
+                Ok(status)
+            }
+            Self::Exited(status) => Ok(*status),
         }
     }
 
@@ -45,8 +68,8 @@ impl VashChild {
                 delegate.wait().await?;
                 Ok(())
             }
-            Self::PreExecuted(_) => Ok(()),
-            Self::Thread(handle) => {
+            Self::PreExecuted(_) | Self::Exited(_) => Ok(()),
+            Self::Thread(handle, _) => {
                 handle.abort();
                 Ok(())
             }
@@ -71,19 +94,140 @@ impl VashChild {
 
                 delegate.wait().await?;
             }
-            Self::PreExecuted(_) => {}
-            Self::Thread(handle) => match signal {
-                Signal::SIGABRT | Signal::SIGINT | Signal::SIGTERM => {
+            Self::PreExecuted(_) | Self::Exited(_) => {}
+            Self::Thread(handle, running) => {
+                let terminate = matches!(
+                    signal,
+                    Signal::SIGABRT | Signal::SIGINT | Signal::SIGTERM | Signal::SIGKILL
+                );
+
+                running.signal(signal, terminate);
+
+                if terminate {
+                    // give whatever got the signal the chance to exit by
+                    // itself before the rest is cut short
+                    while running.busy() && !handle.is_finished() {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+
                     handle.abort();
                 }
-                _ => {}
-            },
+            }
         }
 
         Ok(())
     }
 }
 
+impl Drop for VashChild {
+    fn drop(&mut self) {
+        // the same as `kill_on_drop` for real processes, so that nothing an
+        // adhoc process was running carries on without it
+        if let Self::Thread(handle, _) = self {
+            handle.abort();
+        }
+    }
+}
+
+/// The children an adhoc process is running at the moment, so that signals
+/// sent to the adhoc process reach them.
+#[derive(Clone, Default)]
+pub struct Running(Arc<Mutex<RunningInner>>);
+
+#[derive(Default)]
+struct RunningInner {
+    children: Vec<(usize, RunningChild)>,
+    next_id: usize,
+    /// Set once the adhoc process has been told to terminate, after which
+    /// it can't start anything else.
+    terminated: bool,
+}
+
+#[derive(Clone)]
+enum RunningChild {
+    Process(u32),
+    Thread(Running),
+}
+
+/// Stops keeping track of a child once it is dropped.
+pub struct RunningGuard {
+    running: Running,
+    id: usize,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.running
+            .lock()
+            .children
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl Running {
+    fn lock(&self) -> MutexGuard<'_, RunningInner> {
+        self.0.lock().unwrap()
+    }
+
+    /// Keeps track of `child` until the guard is dropped, or returns `None`
+    /// if the adhoc process has already been told to terminate.
+    pub fn add(&self, child: &VashChild) -> Option<RunningGuard> {
+        let running = match child {
+            VashChild::Process(process) => process.id().map(RunningChild::Process),
+            VashChild::Thread(_, running) => Some(RunningChild::Thread(running.clone())),
+            _ => None,
+        };
+
+        let mut inner = self.lock();
+
+        if inner.terminated {
+            return None;
+        }
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.children.extend(running.map(|running| (id, running)));
+
+        Some(RunningGuard {
+            running: self.clone(),
+            id,
+        })
+    }
+
+    /// Sends `signal` to every process being run, however deeply it is
+    /// nested. With `terminate`, nothing else is started afterwards.
+    fn signal(&self, signal: Signal, terminate: bool) {
+        let children = {
+            let mut inner = self.lock();
+            inner.terminated |= terminate;
+            inner
+                .children
+                .iter()
+                .map(|(_, child)| child.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for child in children {
+            match child {
+                RunningChild::Process(pid) => {
+                    if let Err(err) = kill(Pid::from_raw(pid as i32), signal) {
+                        trace!("failed to send {} to {}: {}", signal, pid, err);
+                    }
+                }
+                RunningChild::Thread(running) => running.signal(signal, terminate),
+            }
+        }
+    }
+
+    /// Whether any real process is still being run.
+    fn busy(&self) -> bool {
+        self.lock().children.iter().any(|(_, child)| match child {
+            RunningChild::Process(_) => true,
+            RunningChild::Thread(running) => running.busy(),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum ChildCommand {
     Signal(Signal),
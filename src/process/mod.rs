@@ -1,6 +1,9 @@
-use std::future::Future;
+use std::{future::Future, io, time::Duration};
 
-use tokio::io::{duplex, split, DuplexStream, ReadHalf, WriteHalf};
+use tokio::{
+    io::{duplex, split, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf},
+    select,
+};
 
 use self::{
     read::ReadSink,
@@ -38,6 +41,14 @@ impl VashProcess {
         }
     }
 
+    /// A process that has already failed after writing `message` to stderr.
+    pub fn failure(message: impl Into<String>) -> Self {
+        VashProcess {
+            stderr: read::VashRead::Canned(message.into().into_bytes()),
+            ..Self::sink_failure()
+        }
+    }
+
     pub fn adhoc_process<F, A>(f: F) -> Self
     where
         F: FnOnce(PseudoChild) -> A + Send + 'static,
@@ -52,10 +63,14 @@ impl VashProcess {
         let (stderr_read, _) = split(stream3);
         let (_, stderr_write) = split(stream4);
 
+        let running = child::Running::default();
+
         let child = PseudoChild {
             stdin: stdin_read,
             stdout: stdout_write,
             stderr: stderr_write,
+            running: running.clone(),
+            unread: Unread::default(),
         };
 
         let handle = tokio::task::spawn(f(child));
@@ -64,13 +79,193 @@ impl VashProcess {
             stdin: write::VashWrite::Duplex(stdin_write),
             stdout: read::VashRead::Duplex(stdout_read),
             stderr: read::VashRead::Duplex(stderr_read),
-            child: child::VashChild::Thread(handle),
+            child: child::VashChild::Thread(handle, running),
         }
     }
+
+    /// Runs this process to completion from inside of an adhoc process,
+    /// feeding it the adhoc process' stdin and streaming its stdout/stderr
+    /// back out as they are produced.
+    pub async fn forward(self, child: &mut PseudoChild) -> VashExitStatus {
+        let VashProcess {
+            stdin,
+            mut stdout,
+            mut stderr,
+            child: mut process,
+        } = self;
+        // once the adhoc process has been told to terminate, nothing new is
+        // started, and it is left to be cancelled
+        let Some(_running) = child.running.add(&process) else {
+            return std::future::pending().await;
+        };
+
+        let PseudoChild {
+            stdin: input,
+            stdout: child_stdout,
+            stderr: child_stderr,
+            unread,
+            ..
+        } = child;
+
+        // the readers are moved into the copies so that they are dropped as
+        // soon as the other end goes away, rather than leaving the process
+        // blocked on a full pipe
+        let stdout = async move {
+            tokio::io::copy(&mut stdout, child_stdout).await.ok();
+        };
+        let stderr = async move {
+            tokio::io::copy(&mut stderr, child_stderr).await.ok();
+        };
+
+        let wait = feed(&mut process, stdin, input, unread);
+
+        let (status, _, _) = tokio::join!(wait, stdout, stderr);
+
+        status.unwrap_or_else(|_| BuiltinExitStatus::new_failure().into())
+    }
+}
+
+/// Feeds `process` the input of the adhoc process running it until it exits.
+///
+/// Input is only ever taken from `unread` once it has been written, and
+/// whatever the process exits without reading is put back, so that it is
+/// there for the next command the same as if they shared a pipe. The
+/// process' stdin is closed once it has read everything up to the end of the
+/// input.
+async fn feed(
+    process: &mut child::VashChild,
+    stdin: write::VashWrite,
+    input: &mut ReadHalf<DuplexStream>,
+    unread: &mut Unread,
+) -> io::Result<VashExitStatus> {
+    // a redirected process has nothing to take
+    if let write::VashWrite::Sink(_) = stdin {
+        return process.wait().await;
+    }
+
+    let mut stdin = Some(stdin);
+    // whether the process stopped reading before its input ran out, in
+    // which case what it didn't get to is left in its stdin until it exits
+    let mut stopped = false;
+    // the most recent part of what the process was given, which is where
+    // anything it didn't read is
+    let mut fed = Vec::new();
+
+    loop {
+        // each step can be cut short by the process exiting without
+        // anything being lost
+        let step = async {
+            let Some(writer) = stdin.as_mut().filter(|_| !stopped) else {
+                return std::future::pending().await;
+            };
+
+            if !unread.buffer.is_empty() {
+                match writer.write(&unread.buffer).await {
+                    Ok(len) => {
+                        fed.extend(unread.buffer.drain(..len));
+                        if fed.len() > 2 * MAX_UNREAD {
+                            fed.drain(..fed.len() - MAX_UNREAD);
+                        }
+
+                        stopped = writer.flush().await.is_err();
+                    }
+                    Err(_) => stopped = true,
+                }
+            } else if !unread.eof {
+                if let Ok(0) | Err(_) = input.read_buf(&mut unread.buffer).await {
+                    unread.eof = true;
+                }
+            } else {
+                // closing stdin throws away whatever is still in the pipe,
+                // so it is only done once that has been read. an adhoc
+                // process' stdin is half of a duplex, which only closes when
+                // it's shut down
+                match writer.unread() {
+                    Some(0) | None => {
+                        writer.shutdown().await.ok();
+                        stdin = None;
+                    }
+                    Some(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        };
+
+        select! {
+            status = process.wait() => {
+                if let Some(writer) = stdin {
+                    let left = writer.unread().unwrap_or(0).min(fed.len());
+                    unread.buffer.splice(0..0, fed.drain(fed.len() - left..));
+                }
+
+                return status;
+            }
+            _ = step => {}
+        }
+    }
+}
+
+/// The most that a process could have left unread in its stdin, which is the
+/// largest a pipe can be by default on Linux.
+const MAX_UNREAD: usize = 1 << 20;
+
+/// The input of an adhoc process that none of the commands it has run have
+/// taken yet.
+#[derive(Default)]
+struct Unread {
+    buffer: Vec<u8>,
+    /// Whether the adhoc process' stdin has reached its end.
+    eof: bool,
 }
 
 pub struct PseudoChild {
     pub stdin: ReadHalf<DuplexStream>,
     pub stdout: WriteHalf<DuplexStream>,
     pub stderr: WriteHalf<DuplexStream>,
+    /// What the adhoc process is running, see [`child::Running`].
+    pub running: child::Running,
+    unread: Unread,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cmd::tests::{run, stdout},
+        context::ShellContext,
+    };
+
+    #[tokio::test]
+    async fn input_is_left_for_the_next_command() {
+        let ctx = ShellContext::from_env();
+
+        assert_eq!(stdout(&ctx, "echo x | { true; cat; }").await, "x\n");
+        assert_eq!(stdout(&ctx, "echo x | (sleep 0.1; cat)").await, "x\n");
+        assert_eq!(
+            stdout(&ctx, "echo x | { cat < /dev/null; cat; }").await,
+            "x\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "seq 1 20000 | { true; sleep 0.1; wc -l; }")
+                .await
+                .trim(),
+            "20000"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_end_of_input_is_passed_on() {
+        let ctx = ShellContext::from_env();
+
+        let output = run(&ctx, "echo x | { cat; cat; echo y; }").await;
+        assert_eq!(output.stdout, "x\ny\n");
+        assert_eq!(output.stderr, "");
+        assert_eq!(output.status, Some(0));
+        assert_eq!(
+            stdout(&ctx, "echo x | while true; do cat; break; done").await,
+            "x\n"
+        );
+        assert_eq!(
+            stdout(&ctx, "while true; do cat; break; done < /dev/null").await,
+            ""
+        );
+    }
 }
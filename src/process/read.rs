@@ -19,6 +19,7 @@ pub enum VashRead {
     Sink(ReadSink),
     Canned(Vec<u8>),
     Duplex(ReadHalf<DuplexStream>),
+    Merged(ReadMerge),
 }
 
 impl From<ChildStdout> for VashRead {
@@ -45,10 +46,13 @@ impl AsyncRead for VashRead {
             Self::Delegate(delegate) => Pin::new(delegate).poll_read(cx, buf),
             Self::Sink(sink) => Pin::new(sink).poll_read(cx, buf),
             Self::Canned(canned) => {
-                buf.put_slice(&std::mem::take(canned));
+                let len = canned.len().min(buf.remaining());
+                buf.put_slice(&canned[..len]);
+                canned.drain(..len);
                 task::Poll::Ready(Ok(()))
             }
             Self::Duplex(duplex) => Pin::new(duplex).poll_read(cx, buf),
+            Self::Merged(merged) => Pin::new(merged).poll_read(cx, buf),
         }
     }
 }
@@ -65,6 +69,61 @@ impl AsyncRead for ReadSink {
     }
 }
 
+/// Interleaves the output of several readers, finishing once all of them have.
+pub struct ReadMerge {
+    readers: Vec<(VashRead, bool)>,
+    next: usize,
+}
+
+impl ReadMerge {
+    pub fn new(readers: impl IntoIterator<Item = VashRead>) -> Self {
+        Self {
+            readers: readers.into_iter().map(|reader| (reader, false)).collect(),
+            next: 0,
+        }
+    }
+}
+
+impl AsyncRead for ReadMerge {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let len = this.readers.len();
+
+        // start after whichever reader produced data last so that a chatty
+        // reader can't starve the others
+        for offset in 0..len {
+            let idx = (this.next + offset) % len;
+            let (reader, done) = &mut this.readers[idx];
+
+            if *done {
+                continue;
+            }
+
+            let filled = buf.filled().len();
+
+            match Pin::new(reader).poll_read(cx, buf) {
+                task::Poll::Ready(Ok(())) if buf.filled().len() == filled => *done = true,
+                task::Poll::Ready(Ok(())) => {
+                    this.next = (idx + 1) % len;
+                    return task::Poll::Ready(Ok(()));
+                }
+                task::Poll::Ready(Err(err)) => return task::Poll::Ready(Err(err)),
+                task::Poll::Pending => {}
+            }
+        }
+
+        if this.readers.iter().all(|(_, done)| *done) {
+            task::Poll::Ready(Ok(()))
+        } else {
+            task::Poll::Pending
+        }
+    }
+}
+
 pub enum ReadMessage {
     Read(Vec<u8>),
     Closed,
@@ -1,5 +1,6 @@
-use std::process::ExitStatus;
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
 
+#[derive(Debug, Clone, Copy)]
 pub enum VashExitStatus {
     Process(ExitStatus),
     Builtin(BuiltinExitStatus),
@@ -25,12 +26,32 @@ impl VashExitStatus {
         }
     }
 
+    /// The signal that killed the process, if it didn't exit by itself.
+    pub fn signal(&self) -> Option<i32> {
+        match self {
+            Self::Process(status) => status.signal(),
+            Self::Builtin(_) => None,
+        }
+    }
+
     pub fn success(&self) -> bool {
         match self {
             Self::Process(status) => status.success(),
             Self::Builtin(status) => status.success(),
         }
     }
+
+    /// The status as seen by `$?`, where a process killed by a signal
+    /// reports 128 plus the signal number.
+    pub fn status_code(&self) -> i32 {
+        match self {
+            Self::Process(status) => status
+                .code()
+                .or_else(|| status.signal().map(|signal| 128 + signal))
+                .unwrap_or(1),
+            Self::Builtin(status) => status.code().unwrap_or(1),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -45,6 +66,10 @@ impl BuiltinExitStatus {
         Self(Some(1))
     }
 
+    pub fn from_code(code: i32) -> Self {
+        Self(Some(code))
+    }
+
     pub fn success(&self) -> bool {
         self.0 == Some(0)
     }
@@ -1,4 +1,4 @@
-use std::{io, pin::Pin, task};
+use std::{io, os::fd::AsRawFd, pin::Pin, task};
 
 use tokio::{
     io::{AsyncWrite, BufWriter, DuplexStream, Sink, WriteHalf},
@@ -14,6 +14,27 @@ pub enum VashWrite {
     Duplex(WriteHalf<DuplexStream>),
 }
 
+// the number of bytes waiting to be read from a pipe, which can be asked of
+// either end of it
+nix::ioctl_read_bad!(fionread, nix::libc::FIONREAD, nix::libc::c_int);
+
+impl VashWrite {
+    /// How much of what has been written is still waiting to be read, or
+    /// `None` if there's no telling.
+    pub fn unread(&self) -> Option<usize> {
+        match self {
+            Self::Stdin(stdin) => {
+                let mut len = 0;
+                // SAFETY: the descriptor is open for as long as `stdin` is,
+                // and the length is all that gets written to
+                unsafe { fionread(stdin.get_ref().as_raw_fd(), &mut len) }.ok()?;
+                Some(stdin.buffer().len() + len as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
 impl From<ChildStdin> for VashWrite {
     fn from(value: ChildStdin) -> Self {
         Self::Stdin(BufWriter::new(value))
@@ -1,25 +1,37 @@
-use std::{io::Write, path::PathBuf};
+use std::io::Write;
 
 use color_eyre::Result;
 use itertools::Itertools;
 use nix::sys::signal::Signal;
 use termion::cursor::Goto;
+use tokio::select;
 
 use crate::{
     cmd::delegate::{Delegate, DelegateCommand, DelegateMessage, ExecutionDelegate},
-    parse::parse_command,
+    context::{Control, ShellContext},
+    job::{BackgroundJob, JobEvent, JobRequest, JobTable},
+    parse::{parse_command, ParseErrorKind},
+    prelude::*,
 };
 
 pub struct State {
     pub prompt: String,
     pub input: String,
+    /// The lines entered so far of a command that isn't complete yet.
+    pub pending: Option<String>,
     pub history: Vec<String>,
     pub history_pos: usize,
     pub output: String,
     pub running: Option<ExecutionDelegate>,
-    pub working_dir: PathBuf,
+    pub jobs: JobTable,
+    /// Where new jobs and requests from job control builtins arrive from,
+    /// see [`ShellContext::set_job_sender`].
+    pub job_requests: Receiver<JobRequest>,
     pub scroll_y: usize,
     pub scrolled_when_len: Option<usize>,
+    pub context: ShellContext,
+    /// The status vash exits with, once `exit` has been run.
+    pub exit: Option<i32>,
 }
 
 impl State {
@@ -33,29 +45,38 @@ impl State {
             termion::cursor::Goto(1, 1)
         )?;
 
-        write!(stdout, "{}", self.prompt)?;
+        let continuation = self.continuation_prompt();
+        let mut prompt = self.prompt.as_str();
+        let mut row = 1;
 
-        write!(stdout, "{}", self.input)?;
+        // the lines of an incomplete command stay above the one being typed
+        for line in self.pending.iter().flat_map(|pending| pending.lines()) {
+            write!(stdout, "{}{}{}", Goto(1, row), prompt, line)?;
+            prompt = &continuation;
+            row += 1;
+        }
+
+        write!(stdout, "{}{}{}", Goto(1, row), prompt, self.input)?;
 
         let output = self.output.lines().collect_vec();
 
         let len = self.scrolled_when_len.unwrap_or(output.len());
 
-        let available = height as usize - 2;
+        let available = (height as usize).saturating_sub(row as usize + 1);
         let start = len.saturating_sub(available).saturating_sub(self.scroll_y);
         let end = len.saturating_sub(self.scroll_y);
 
         let lines = &output[start..end];
 
         for (x, line) in lines.iter().enumerate() {
-            write!(stdout, "{}", Goto(1, (x + 2) as u16))?;
+            write!(stdout, "{}", Goto(1, x as u16 + row + 1))?;
             write!(stdout, "{}", line)?;
         }
 
         write!(
             stdout,
             "{}",
-            Goto((self.prompt.len() + self.input.len()) as u16 + 1, 1)
+            Goto((prompt.len() + self.input.len()) as u16 + 1, row)
         )?;
 
         stdout.flush()?;
@@ -63,20 +84,51 @@ impl State {
         Ok(())
     }
 
-    pub async fn execute(&mut self) -> Result<()> {
+    /// The prompt shown while a command is being continued onto more lines,
+    /// taken from `$PS2`.
+    fn continuation_prompt(&self) -> String {
+        self.context
+            .var("PS2")
+            .unwrap_or_else(|| String::from("> "))
+    }
+
+    pub async fn execute(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.input = format!("{}\n{}", pending, self.input);
+        }
+
         let res = parse_command(&self.input);
 
-        self.push_history();
+        let plan = match res {
+            Ok(plan) => plan,
+            // a blank line just gives a new prompt
+            Err(err) if matches!(err.kind, ParseErrorKind::Empty) => {
+                self.input.clear();
+                return;
+            }
+            // keep reading lines until whatever was left open is closed
+            Err(err) if err.is_incomplete() => {
+                self.pending = Some(std::mem::take(&mut self.input));
+                return;
+            }
+            Err(err) => {
+                trace!("failed to parse command: {:?}", err);
+                self.push_output(&err.diagnostic(&self.input));
+                self.context.set_last_status(2);
+                self.push_history();
+                return;
+            }
+        };
 
-        let plan = res?;
+        self.push_history();
 
         trace!("parsed command: {:?}", plan);
 
-        let exec = plan.execute().await;
+        // `&&` and `||` wait for their left side before going any further,
+        // which can't hold up the shell
+        let exec = plan.execute_lazily(&self.context);
 
         self.running = Some(ExecutionDelegate::spawn(exec).await);
-
-        Ok(())
     }
 
     pub fn push_history(&mut self) {
@@ -115,6 +167,17 @@ impl State {
         }
     }
 
+    /// Throws away an incomplete command, returning `false` if there wasn't
+    /// one.
+    pub fn cancel_pending(&mut self) -> bool {
+        if self.pending.take().is_some() {
+            self.input.clear();
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn terminate(&mut self) -> bool {
         if let Some(running) = self.running.take() {
             running.send(DelegateCommand::Signal(Signal::SIGTERM));
@@ -152,23 +215,48 @@ impl State {
     }
 
     pub async fn poll(&mut self) {
-        if let Some(msg) = self.running.recv().await {
-            match msg {
+        select! {
+            Some(msg) = self.running.recv() => match msg {
                 DelegateMessage::Stdout(data) => {
                     self.push_output(&String::from_utf8_lossy(&data));
                 }
                 DelegateMessage::Stderr(data) => {
                     self.push_output(&String::from_utf8_lossy(&data));
                 }
-                DelegateMessage::Exit(code) => {
-                    self.push_output(&format!("exit: {:#?}\n", code));
+                DelegateMessage::Exit(status) => {
+                    self.push_output(&format!("exit: {:#?}\n", status.code()));
+                    self.context.set_last_status(status.status_code());
                     self.running = None;
+
+                    if let Some(Control::Exit(status)) = self.context.take_control() {
+                        self.exit = Some(status);
+                    }
                 }
                 DelegateMessage::Error(err) => {
                     self.push_output(&format!("error: {:#?}\n", err));
                     self.running = None;
                 }
-            }
+            },
+            event = self.jobs.next() => match event {
+                JobEvent::Output(data) => self.push_output(&String::from_utf8_lossy(&data)),
+                JobEvent::Finished(line) => self.push_output(&line),
+            },
+            Some(request) = self.job_requests.recv() => match request {
+                JobRequest::Spawn(job) => self.add_job(*job).await,
+                JobRequest::Run(f) => f(&mut self.jobs),
+            },
+        }
+    }
+
+    /// Starts keeping track of `job`, announcing its number and process id.
+    async fn add_job(&mut self, job: BackgroundJob) {
+        let pid = job.process.child.id();
+        let delegate = ExecutionDelegate::spawn(job.process).await;
+        let id = self.jobs.add(job.command, pid, delegate);
+
+        match pid {
+            Some(pid) => self.push_output(&format!("[{}] {}\n", id, pid)),
+            None => self.push_output(&format!("[{}]\n", id)),
         }
     }
 }